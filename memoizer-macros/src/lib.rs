@@ -0,0 +1,475 @@
+//! # Memoizer Macros
+//! The `#[memoize]` attribute, which memoizes a function without a [`Memoizer`](https://docs.rs/memoizer) having to be created and passed around. Usually used through the `macros` feature of the `memoizer` crate, as `memoizer::memoize`.
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+// Imports
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{parse_macro_input, Error, Expr, FnArg, ItemFn, Pat, ReturnType, Token, Type};
+
+/// Memoizes a function, caching its return value for each combination of arguments in a hidden cache next to it. Calls made while the function is running, including recursive ones, go through the cache too.
+///
+/// Every argument must be `Eq + Hash + Clone + 'static` and the return value `Clone`. Generic functions aren't supported.
+///
+/// Two more functions are generated alongside, with the same visibility: `<name>_cache_clear()` removes every cached value, and `<name>_cache_stats()` returns a `memoizer::CacheStats` counting hits and misses.
+///
+/// # Options
+///
+/// * `capacity = <usize>` holds on to at most that many values, evicting the least recently used one.
+/// * `ttl = <Duration>` computes values again once they are that old.
+/// * `sync` shares one cache between every thread, behind a lock which isn't held while the function runs. By default each thread has its own cache.
+///
+/// # Methods
+///
+/// Methods need to be told how to tell instances apart, in one of two ways:
+///
+/// * `key = <expression>: <type>` projects `self` onto part of the key, such as an id field, and caches values for every instance together like a function would. The `<name>_cache_clear()` and `<name>_cache_stats()` functions are generated as associated functions.
+/// * `field = <name>` keeps the cache in a `memoizer::MethodMemoizer` field of the instance, keyed on the arguments alone. The field decides the capacity and time to live, and has its own `clear` and `stats`.
+///
+/// Caches not kept in a field are statics in a hidden function generated next to the memoized one, which has two consequences:
+///
+/// * The argument, key and return types can't mention `Self`, which statics can't use. Name the type instead.
+/// * Methods in trait impls can only be memoized with `field`, since a trait impl can't hold the extra functions.
+///
+/// ```compile_fail
+///# use memoizer_macros::memoize;
+/// struct Circle {
+///     radius: u64,
+/// }
+///
+/// impl Circle {
+///     // error: the return type mentions `Self`
+///     #[memoize(key = self.radius: u64)]
+///     fn doubled(&self) -> Self {
+///         Circle { radius: self.radius * 2 }
+///     }
+/// }
+/// ```
+///
+/// # Examples
+///
+/// ```
+///# use memoizer_macros::memoize;
+/// #[memoize]
+/// fn fib(n: u64) -> u64 {
+///     if n < 2 {
+///         n
+///     } else {
+///         fib(n - 1) + fib(n - 2)
+///     }
+/// }
+///
+/// #[memoize(capacity = 100, ttl = std::time::Duration::from_secs(60), sync)]
+/// fn cost(from: u32, to: u32, weight: u32) -> u32 {
+///     (to - from) * weight
+/// }
+///
+/// assert_eq!(12_586_269_025, fib(50));
+/// assert_eq!(51, fib_cache_stats().len);
+/// assert_eq!(30, cost(1, 4, 10));
+/// cost_cache_clear();
+/// ```
+///
+/// Methods:
+///
+/// ```
+///# use memoizer_macros::memoize;
+/// use memoizer::MethodMemoizer;
+///
+/// struct Model {
+///     id: u64,
+///     weights: Vec<f64>,
+///     scores: MethodMemoizer<(u32,), f64>,
+/// }
+///
+/// impl Model {
+///     #[memoize(key = self.id: u64)]
+///     fn norm(&self) -> f64 {
+///         self.weights.iter().map(|w| w * w).sum::<f64>().sqrt()
+///     }
+///
+///     #[memoize(field = scores)]
+///     fn score(&self, x: u32) -> f64 {
+///         self.weights.iter().map(|w| w * x as f64).sum()
+///     }
+/// }
+///
+/// let model = Model {
+///     id: 7,
+///     weights: vec![3.0, 4.0],
+///     scores: MethodMemoizer::new(),
+/// };
+/// assert_eq!(5.0, model.norm());
+/// assert_eq!(14.0, model.score(2));
+/// assert_eq!(1, Model::norm_cache_stats().len);
+/// assert_eq!(1, model.scores.stats().len);
+/// ```
+///
+#[proc_macro_attribute]
+pub fn memoize(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = Options::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("capacity") {
+            options.capacity = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("ttl") {
+            options.ttl = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("sync") {
+            options.sync = true;
+        } else if meta.path.is_ident("key") {
+            let value = meta.value()?;
+            let projection = value.parse()?;
+            value.parse::<Token![:]>()?;
+            options.key = Some((projection, value.parse()?));
+        } else if meta.path.is_ident("field") {
+            options.field = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("expected `capacity`, `ttl`, `sync`, `key` or `field`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+
+    let function = parse_macro_input!(item as ItemFn);
+    expand(options, function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/* The options given to the attribute */
+#[derive(Default)]
+struct Options {
+    capacity: Option<Expr>,
+    ttl: Option<Expr>,
+    sync: bool,
+    key: Option<(Expr, Type)>,
+    field: Option<Ident>,
+}
+
+/* Moves the function's body into a closure taking its arguments as a tuple,
+ * which the cache calls on a miss. The function itself only looks the tuple
+ * up in the cache: a hidden static, or a field for methods.
+ */
+fn expand(options: Options, function: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    if let Some(constness) = &sig.constness {
+        return Err(Error::new_spanned(
+            constness,
+            "const functions can't be memoized",
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "async functions can't be memoized, use memoizer::AsyncMemoizer instead",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "generic functions can't be memoized",
+        ));
+    }
+
+    let mut receiver = None;
+    let mut patterns = Vec::new();
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for input in &sig.inputs {
+        let typed = match input {
+            FnArg::Typed(typed) => typed,
+            FnArg::Receiver(self_) => {
+                receiver = Some(self_);
+                continue;
+            }
+        };
+        match &*typed.pat {
+            Pat::Ident(pat) if pat.subpat.is_none() => names.push(pat.ident.clone()),
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "arguments of memoized functions must be named",
+                ))
+            }
+        }
+        patterns.push(&typed.pat);
+        types.push(&typed.ty);
+    }
+    let output: Type = match &sig.output {
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+
+    // The function keeps its signature, minus any `mut` on its arguments
+    let mut outer = sig.clone();
+    for (input, name) in outer
+        .inputs
+        .iter_mut()
+        .filter(|input| matches!(input, FnArg::Typed(_)))
+        .zip(&names)
+    {
+        if let FnArg::Typed(typed) = input {
+            *typed.pat = syn::parse_quote!(#name);
+        }
+    }
+
+    let arguments = quote!((#(#types,)*));
+    let compute = quote!(|(#(#patterns,)*): #arguments| -> #output #block);
+
+    // Methods keeping their cache in a field need nothing else generated
+    if let Some(field) = &options.field {
+        if receiver.is_none() {
+            return Err(Error::new_spanned(
+                field,
+                "only methods can be memoized in a field",
+            ));
+        }
+        if options.capacity.is_some()
+            || options.ttl.is_some()
+            || options.sync
+            || options.key.is_some()
+        {
+            return Err(Error::new_spanned(
+                field,
+                "methods memoized in a field take their options from the field",
+            ));
+        }
+        return Ok(quote! {
+            #(#attrs)*
+            #vis #outer {
+                self.#field.value((#(#names,)*), #compute)
+            }
+        });
+    }
+
+    // Statics can't refer to the Self of an impl they are nested in
+    let key_type = options.key.as_ref().map(|(_, ty)| ty);
+    for ty in types
+        .iter()
+        .map(|ty| &***ty)
+        .chain(key_type)
+        .chain(Some(&output))
+    {
+        if let Some(self_) = find_self(ty.to_token_stream()) {
+            return Err(Error::new(
+                self_.span(),
+                "memoized functions can't mention `Self` in their arguments, key or return type, name the type instead",
+            ));
+        }
+    }
+
+    let (key, value, compute) = match (&options.key, receiver) {
+        (None, None) => (arguments, quote!((#(#names,)*)), compute),
+        (Some((projection, ty)), Some(_)) => (
+            quote!((#ty, #(#types,)*)),
+            quote!((#projection, #(#names,)*)),
+            quote!(|(_, #(#patterns,)*): (#ty, #(#types,)*)| -> #output #block),
+        ),
+        (Some((projection, _)), None) => {
+            return Err(Error::new_spanned(
+                projection,
+                "only methods can project `self` onto the key",
+            ))
+        }
+        (None, Some(receiver)) => {
+            return Err(Error::new_spanned(
+                receiver,
+                "memoized methods need a `key = <expression>: <type>` projecting `self`, or the `field = <name>` their cache is kept in",
+            ))
+        }
+    };
+
+    // The cache is a static in a hidden function, which can live in an impl
+    let name = &sig.ident;
+    let hidden = format_ident!("__memoize_{}", name);
+    let cache = match receiver {
+        Some(_) => quote!(Self::#hidden()),
+        None => quote!(#hidden()),
+    };
+    let clear = format_ident!("{}_cache_clear", name);
+    let stats = format_ident!("{}_cache_stats", name);
+    let clear_doc = format!("Removes every value cached by `{}`.", name);
+    let stats_doc = format!("Returns how often `{}` found its value cached.", name);
+
+    let capacity = optional(options.capacity);
+    let ttl = optional(options.ttl);
+    let new = quote! {
+        ::memoizer::__private::Cache::new(#capacity, #ttl)
+    };
+    let (declaration, value, clear_body, stats_body) = if options.sync {
+        (
+            quote! {
+                fn #hidden() -> &'static ::memoizer::__private::Global<#key, #output> {
+                    static CACHE: ::memoizer::__private::Global<#key, #output> =
+                        ::memoizer::__private::Global::new(|| #new);
+                    &CACHE
+                }
+            },
+            quote!(#cache.value(#value, #compute)),
+            quote!(#cache.lock().clear()),
+            quote!(#cache.lock().stats()),
+        )
+    } else {
+        (
+            quote! {
+                fn #hidden() -> &'static ::std::thread::LocalKey<
+                    ::std::cell::RefCell<::memoizer::__private::Cache<#key, #output>>,
+                > {
+                    ::std::thread_local! {
+                        static CACHE: ::std::cell::RefCell<::memoizer::__private::Cache<#key, #output>> =
+                            ::std::cell::RefCell::new(#new);
+                    }
+                    &CACHE
+                }
+            },
+            quote!(::memoizer::__private::Cache::local(#cache, #value, #compute)),
+            quote!(#cache.with(|cache| cache.borrow_mut().clear())),
+            quote!(#cache.with(|cache| cache.borrow().stats())),
+        )
+    };
+
+    Ok(quote! {
+        #[doc(hidden)]
+        #declaration
+
+        #(#attrs)*
+        #vis #outer {
+            #value
+        }
+
+        #[doc = #clear_doc]
+        #vis fn #clear() {
+            #clear_body
+        }
+
+        #[doc = #stats_doc]
+        #vis fn #stats() -> ::memoizer::CacheStats {
+            #stats_body
+        }
+    })
+}
+
+/* Finds the first `Self` in some tokens, looking inside any groups */
+fn find_self(tokens: TokenStream2) -> Option<Ident> {
+    tokens.into_iter().find_map(|token| match token {
+        TokenTree::Ident(ident) if ident == "Self" => Some(ident),
+        TokenTree::Group(group) => find_self(group.stream()),
+        _ => None,
+    })
+}
+
+/* Wraps an optional expression in Some, or gives None */
+fn optional(expr: Option<Expr>) -> TokenStream2 {
+    match expr {
+        Some(expr) => quote!(::std::option::Option::Some(#expr)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(options: Options, function: &str) -> Result<String, String> {
+        let function = syn::parse_str(function).expect("function parses");
+        expand(options, function)
+            .map(|tokens| tokens.to_string())
+            .map_err(|error| error.to_string())
+    }
+
+    /* Functions which can't be cached in a static are rejected */
+    #[test]
+    fn unsupported() {
+        let error = |function| expand_str(Options::default(), function).unwrap_err();
+        assert_eq!(
+            "generic functions can't be memoized",
+            error("fn f<T>(t: T) {}")
+        );
+        assert!(error("fn f(&self) {}").starts_with("memoized methods need a `key"));
+        assert_eq!(
+            "arguments of memoized functions must be named",
+            error("fn f((a, b): (u8, u8)) {}")
+        );
+        assert!(error("async fn f() {}").starts_with("async functions"));
+        assert!(error("fn f(a: Vec<Self>) {}").contains("`Self`"));
+
+        let key = Options {
+            key: Some((syn::parse_quote!(self.id), syn::parse_quote!(u64))),
+            ..Options::default()
+        };
+        assert!(expand_str(key, "fn f(&self) -> Option<Self> { None }")
+            .unwrap_err()
+            .contains("`Self`"));
+
+        let field = Options {
+            field: Some(syn::parse_quote!(cache)),
+            ..Options::default()
+        };
+        assert_eq!(
+            "only methods can be memoized in a field",
+            expand_str(field, "fn f() {}").unwrap_err()
+        );
+    }
+
+    /* Arguments lose their `mut` in the signature but keep it in the body */
+    #[test]
+    fn arguments() {
+        let tokens = expand_str(
+            Options::default(),
+            "fn f(mut a: u8, b: u8) -> u8 { a += b; a }",
+        )
+        .expect("expands");
+        assert!(tokens.contains("| (mut a , b ,) : (u8 , u8 ,) | -> u8"));
+        assert!(tokens.contains("fn f (a : u8 , b : u8) -> u8"));
+    }
+
+    /* Options end up as arguments of the cache */
+    #[test]
+    fn options() {
+        let options = Options {
+            capacity: Some(syn::parse_quote!(10)),
+            sync: true,
+            ..Options::default()
+        };
+        let tokens = expand_str(options, "fn f() {}").expect("expands");
+        assert!(tokens.contains("static CACHE : :: memoizer :: __private :: Global < () , () >"));
+        assert!(tokens.contains(
+            "Cache :: new (:: std :: option :: Option :: Some (10) , :: std :: option :: Option :: None)"
+        ));
+    }
+
+    /* Methods find their cache through Self, or in a field */
+    #[test]
+    fn methods() {
+        let key = Options {
+            key: Some((syn::parse_quote!(self.id), syn::parse_quote!(u64))),
+            ..Options::default()
+        };
+        let tokens = expand_str(key, "fn f(&self, a: u8) -> u8 { a }").expect("expands");
+        assert!(tokens.contains("Cache :: local (Self :: __memoize_f () , (self . id , a ,)"));
+        assert!(tokens.contains("| (_ , a ,) : (u64 , u8 ,) | -> u8"));
+
+        let field = Options {
+            field: Some(syn::parse_quote!(cache)),
+            ..Options::default()
+        };
+        let tokens = expand_str(field, "fn f(&self, a: u8) -> u8 { a }").expect("expands");
+        assert!(tokens.contains("self . cache . value ((a ,) ,"));
+        assert!(!tokens.contains("cache_stats"));
+    }
+}
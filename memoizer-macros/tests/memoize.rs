@@ -0,0 +1,150 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use memoizer_macros::memoize;
+
+#[memoize]
+fn fib(n: u64) -> u64 {
+    if n < 2 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+/* Recursive calls are answered from the cache */
+#[test]
+fn recursive() {
+    assert_eq!(12_586_269_025, fib(50));
+    let stats = fib_cache_stats();
+    assert_eq!(51, stats.misses);
+    assert_eq!(48, stats.hits);
+
+    fib_cache_clear();
+    assert_eq!(0, fib_cache_stats().len);
+}
+
+#[memoize(capacity = 2)]
+fn join(mut a: String, b: &'static str, times: usize) -> String {
+    a.push_str(&b.repeat(times));
+    a
+}
+
+/* Every argument is part of the key, and the capacity is kept to */
+#[test]
+fn arguments() {
+    assert_eq!("gaygirlsgirls", join(String::from("gay"), "girls", 2));
+    assert_eq!("gaygirls", join(String::from("gay"), "girls", 1));
+    assert_eq!("gaygirlsgirls", join(String::from("gay"), "girls", 2));
+    assert_eq!(1, join_cache_stats().hits);
+
+    join(String::new(), "gay", 1);
+    assert_eq!(2, join_cache_stats().len);
+}
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[memoize(sync, ttl = Duration::from_secs(3600))]
+fn square(n: u64) -> u64 {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    n * n
+}
+
+/* One cache is shared between threads */
+#[test]
+fn sync() {
+    assert_eq!(49, square(7));
+    let handles: Vec<_> = (0..4).map(|_| thread::spawn(|| square(7))).collect();
+    for handle in handles {
+        assert_eq!(49, handle.join().unwrap());
+    }
+    assert_eq!(1, CALLS.load(Ordering::SeqCst));
+    assert_eq!(4, square_cache_stats().hits);
+}
+
+#[memoize(ttl = Duration::ZERO)]
+fn now() -> usize {
+    static TICKS: AtomicUsize = AtomicUsize::new(0);
+    TICKS.fetch_add(1, Ordering::SeqCst)
+}
+
+/* Values which have expired are computed again */
+#[test]
+fn ttl() {
+    assert_ne!(now(), now());
+    assert_eq!(2, now_cache_stats().misses);
+}
+
+struct Account {
+    id: u64,
+    balance: u64,
+    interest: memoizer::MethodMemoizer<(u32,), u64>,
+}
+
+impl Account {
+    #[memoize(key = self.id: u64)]
+    fn digits(&self) -> u32 {
+        self.balance.to_string().len() as u32
+    }
+
+    #[memoize(field = interest)]
+    fn interest(&self, years: u32) -> u64 {
+        match years {
+            0 => self.balance,
+            years => self.interest(years - 1) * 21 / 20,
+        }
+    }
+}
+
+/* Methods are cached across instances by a projection of self, or within
+ * each instance in a field.
+ */
+#[test]
+fn methods() {
+    let account = |id, balance| Account {
+        id,
+        balance,
+        interest: memoizer::MethodMemoizer::new(),
+    };
+    let (first, second) = (account(1, 1000), account(2, 100));
+    assert_eq!(4, first.digits());
+    assert_eq!(3, second.digits());
+    assert_eq!(4, account(1, 5).digits());
+    assert_eq!(1, Account::digits_cache_stats().hits);
+    Account::digits_cache_clear();
+
+    assert_eq!(1623, first.interest(10));
+    assert_eq!(158, second.interest(10));
+    assert_eq!(11, first.interest.stats().len);
+    assert_eq!(100, second.interest(0));
+}
+
+trait Area {
+    fn area(&self, scale: u64) -> u64;
+}
+
+struct Square {
+    side: u64,
+    areas: memoizer::MethodMemoizer<(u64,), u64>,
+}
+
+/* Trait impls can't hold the functions generated for a static cache */
+impl Area for Square {
+    #[memoize(field = areas)]
+    fn area(&self, scale: u64) -> u64 {
+        (self.side * scale).pow(2)
+    }
+}
+
+/* Methods of trait impls are memoized in a field */
+#[test]
+fn trait_methods() {
+    let square = Square {
+        side: 3,
+        areas: memoizer::MethodMemoizer::new(),
+    };
+    assert_eq!(36, square.area(2));
+    assert_eq!(36, square.area(2));
+    assert_eq!(1, square.areas.stats().hits);
+}
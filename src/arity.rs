@@ -0,0 +1,483 @@
+//! Memoization of functions taking more or less than one argument.
+
+// Imports
+use std::hash::Hash;
+use std::time::Duration;
+
+use crate::policy::{Lru, Policy};
+use crate::refresh::Refresher;
+use crate::store::Store;
+use crate::{Clock, Entry, HeapSize, Spawn};
+
+macro_rules! memoizer {
+    ($(#[$meta:meta])* $name:ident, $arguments:literal, ($($T:ident $arg:ident),*)) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name<$($T,)* V, F, P = Lru<($($T,)*)>>
+        where
+            $($T: Eq + Hash + Clone,)*
+            F: Fn($($T),*) -> V,
+            P: Policy<($($T,)*)>,
+        {
+            function: F,
+            store: Store<($($T,)*), V, P>,
+        }
+
+        impl<$($T,)* V, F> $name<$($T,)* V, F>
+        where
+            $($T: Eq + Hash + Clone,)*
+            F: Fn($($T),*) -> V,
+        {
+            #[doc = concat!("Creates a new ", stringify!($name), " given a function taking ", $arguments, ".")]
+            pub fn new(function: F) -> $name<$($T,)* V, F> {
+                $name {
+                    function,
+                    store: Store::new(None, Lru::new()),
+                }
+            }
+
+            #[doc = concat!("Creates a new ", stringify!($name), " given a function taking ", $arguments, ", which holds on to at most `capacity` values by evicting the least recently used one.")]
+            pub fn with_capacity_lru(capacity: usize, function: F) -> $name<$($T,)* V, F> {
+                $name::with_policy(capacity, Lru::new(), function)
+            }
+        }
+
+        impl<$($T,)* V, F, P> $name<$($T,)* V, F, P>
+        where
+            $($T: Eq + Hash + Clone,)*
+            F: Fn($($T),*) -> V,
+            P: Policy<($($T,)*)>,
+        {
+            #[doc = concat!("Creates a new ", stringify!($name), " given a function taking ", $arguments, ", which holds on to at most `capacity` values by evicting the ones chosen by `policy`. See [`Memoizer::with_policy`](crate::Memoizer::with_policy).")]
+            pub fn with_policy(capacity: usize, policy: P, function: F) -> $name<$($T,)* V, F, P> {
+                $name {
+                    function,
+                    store: Store::new(Some(capacity), policy),
+                }
+            }
+
+            /// Computes values again once `ttl` has passed since they were computed. See [`Memoizer::with_ttl`](crate::Memoizer::with_ttl).
+            pub fn with_ttl(mut self, ttl: Duration) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_ttl(ttl);
+                self
+            }
+
+            /// Picks a time to live for each value from its arguments and the value itself. See [`Memoizer::with_expiry`](crate::Memoizer::with_expiry).
+            pub fn with_expiry(
+                mut self,
+                expiry: fn(&($($T,)*), &V) -> Option<Duration>,
+            ) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_expiry(expiry);
+                self
+            }
+
+            /// Uses `clock` to tell when values expire. See [`Memoizer::with_clock`](crate::Memoizer::with_clock).
+            pub fn with_clock<T: Clock + 'static>(mut self, clock: T) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_clock(clock);
+                self
+            }
+
+            /// Keeps serving values for up to `stale` after they expire, while they are recomputed in the background. See [`Memoizer::with_stale_while_revalidate`](crate::Memoizer::with_stale_while_revalidate).
+            pub fn with_stale_while_revalidate(mut self, stale: Duration) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_stale_while_revalidate(stale);
+                self
+            }
+
+            /// Recomputes values in the background when they are asked for less than `ahead` before they expire. See [`Memoizer::with_refresh_ahead`](crate::Memoizer::with_refresh_ahead).
+            pub fn with_refresh_ahead(mut self, ahead: Duration) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_refresh_ahead(ahead);
+                self
+            }
+
+            /// Recomputes values probabilistically before they expire. See [`Memoizer::with_early_expiration`](crate::Memoizer::with_early_expiration).
+            pub fn with_early_expiration(mut self, beta: f64) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_early_expiration(beta);
+                self
+            }
+
+            /// Shortens the time to live of each value by a random amount, up to `fraction` of it. See [`Memoizer::with_ttl_jitter`](crate::Memoizer::with_ttl_jitter).
+            pub fn with_ttl_jitter(mut self, fraction: f64) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_ttl_jitter(fraction);
+                self
+            }
+
+            /// Returns the maximum number of values the memoizer holds on to, or None if it is unbounded.
+            pub fn capacity(&self) -> Option<usize> {
+                self.store.capacity()
+            }
+
+            /// Returns the maximum number of bytes the memoizer's arguments and values may take up, or None if it has no memory budget.
+            pub fn memory_budget(&self) -> Option<usize> {
+                self.store.memory_budget()
+            }
+
+            /// Returns the estimated number of bytes taken up by the memoizer's arguments and values, or None if it has no memory budget and isn't keeping track.
+            pub fn memory_used(&self) -> Option<usize> {
+                self.store.memory_used()
+            }
+
+            /// Returns a reference to the value for the memoized function, computing it if it isn't cached yet. See [`Memoizer::value_ref`](crate::Memoizer::value_ref).
+            // The arguments are spread out on purpose, so there can be plenty
+            #[allow(clippy::too_many_arguments)]
+            pub fn value_ref(&mut self, $($arg: $T),*) -> &V {
+                let function = &self.function;
+                self.store
+                    .value_ref(($($arg,)*), |($($arg,)*)| function($($arg),*))
+            }
+
+            /// Returns the [`Entry`] for the arguments, keyed on a tuple of them. See [`Memoizer::entry`](crate::Memoizer::entry).
+            #[allow(clippy::too_many_arguments)]
+            pub fn entry(
+                &mut self,
+                $($arg: $T),*
+            ) -> Entry<'_, ($($T,)*), V, impl FnOnce(($($T,)*)) -> V + '_, P> {
+                let function = &self.function;
+                Entry::new(&mut self.store, ($($arg,)*), move |($($arg,)*)| {
+                    function($($arg),*)
+                })
+            }
+
+            /// Removes every value which has expired, returning how many there were. See [`Memoizer::purge_expired`](crate::Memoizer::purge_expired).
+            pub fn purge_expired(&mut self) -> usize {
+                self.store.purge_expired()
+            }
+
+            /// Returns whether a value for the arguments is cached and hasn't expired, without computing it or counting as a use.
+            #[allow(clippy::too_many_arguments)]
+            pub fn contains(&self, $($arg: $T),*) -> bool {
+                self.store.contains(&($($arg,)*))
+            }
+
+            /// Returns the cached value for the arguments if there is one which hasn't expired, without computing it or counting as a use.
+            #[allow(clippy::too_many_arguments)]
+            pub fn peek(&self, $($arg: $T),*) -> Option<&V> {
+                self.store.peek(&($($arg,)*))
+            }
+
+            /// Caches a value for the arguments without calling the function, returning the value it replaced if there was one. See [`Memoizer::insert`](crate::Memoizer::insert).
+            #[allow(clippy::too_many_arguments)]
+            pub fn insert(&mut self, $($arg: $T,)* value: V) -> Option<V> {
+                self.store.insert(($($arg,)*), value)
+            }
+
+            /// Caches a value for the arguments without calling the function, unless a value which hasn't expired is cached already. Returns whether the value was cached. See [`Memoizer::prime`](crate::Memoizer::prime).
+            #[allow(clippy::too_many_arguments)]
+            pub fn prime(&mut self, $($arg: $T,)* value: V) -> bool {
+                self.store.prime(($($arg,)*), value)
+            }
+
+            /// Removes the value for the arguments, returning it if it was cached. See [`Memoizer::invalidate`](crate::Memoizer::invalidate).
+            #[allow(clippy::too_many_arguments)]
+            pub fn invalidate(&mut self, $($arg: $T),*) -> Option<V> {
+                self.store.invalidate(&($($arg,)*))
+            }
+
+            /// Removes every cached value.
+            pub fn clear(&mut self) {
+                self.store.clear();
+            }
+
+            /// Returns the number of cached values, including expired ones which haven't been removed yet.
+            pub fn len(&self) -> usize {
+                self.store.len()
+            }
+
+            /// Returns whether there are no cached values, including expired ones which haven't been removed yet.
+            pub fn is_empty(&self) -> bool {
+                self.store.is_empty()
+            }
+
+            /// Iterates over the cached arguments, as tuples, and values which haven't expired, in no particular order. See [`Memoizer::iter`](crate::Memoizer::iter).
+            pub fn iter(&self) -> impl Iterator<Item = (&($($T,)*), &V)> + '_ {
+                self.store.iter()
+            }
+
+            /// Iterates over the cached arguments, as tuples, whose values haven't expired, in no particular order.
+            pub fn keys(&self) -> impl Iterator<Item = &($($T,)*)> + '_ {
+                self.store.iter().map(|(key, _)| key)
+            }
+
+            /// Keeps only the cached values for which `keep` returns true, given the arguments as a tuple and the value, removing the rest.
+            pub fn retain<R>(&mut self, keep: R)
+            where
+                R: FnMut(&($($T,)*), &V) -> bool,
+            {
+                self.store.retain(keep);
+            }
+
+            /// Removes every cached value, returning the arguments, as tuples, and values in no particular order. See [`Memoizer::drain`](crate::Memoizer::drain).
+            pub fn drain(&mut self) -> impl Iterator<Item = (($($T,)*), V)> + '_ {
+                self.store.drain()
+            }
+        }
+
+        impl<$($T,)* V, F, P> $name<$($T,)* V, F, P>
+        where
+            $($T: Eq + Hash + Clone,)*
+            V: Clone,
+            F: Fn($($T),*) -> V,
+            P: Policy<($($T,)*)>,
+        {
+            /// Returns the value for the memoized function, computing it if it isn't cached yet.
+            #[allow(clippy::too_many_arguments)]
+            pub fn value(&mut self, $($arg: $T),*) -> V {
+                self.value_ref($($arg),*).clone()
+            }
+        }
+
+        impl<$($T,)* V, F, P> $name<$($T,)* V, F, P>
+        where
+            $($T: Eq + Hash + Clone + HeapSize,)*
+            V: HeapSize,
+            F: Fn($($T),*) -> V,
+            P: Policy<($($T,)*)>,
+        {
+            /// Limits the estimated number of bytes taken up by the memoizer's arguments and values. See [`Memoizer::with_memory_budget`](crate::Memoizer::with_memory_budget).
+            pub fn with_memory_budget(mut self, bytes: usize) -> $name<$($T,)* V, F, P> {
+                self.store = self.store.with_memory_budget(bytes);
+                self
+            }
+        }
+
+        impl<$($T,)* V, F, P> $name<$($T,)* V, F, P>
+        where
+            $($T: Eq + Hash + Clone + Send + 'static,)*
+            V: Send + 'static,
+            F: Fn($($T),*) -> V + Clone + Send + Sync + 'static,
+            P: Policy<($($T,)*)>,
+        {
+            /// Lets the memoizer recompute values in the background with a copy of its function, running them on `spawner`. See [`Memoizer::with_refresher`](crate::Memoizer::with_refresher).
+            pub fn with_refresher<S: Spawn + 'static>(mut self, spawner: S) -> $name<$($T,)* V, F, P> {
+                let function = self.function.clone();
+                let refresher = Refresher::new(move |($($arg,)*)| function($($arg),*), spawner);
+                self.store = self.store.with_refresher(refresher);
+                self
+            }
+        }
+    };
+}
+
+memoizer!(
+    /// Memoizes a function taking no arguments, so it is only called once. Not much use on its own, but can be bounded and given a time to live like any other memoizer, which makes it a handy way to cache a single expensive value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer0;
+    /// use std::time::Duration;
+    ///
+    /// let mut config = Memoizer0::new(|| String::from("gay = true")).with_ttl(Duration::from_secs(60));
+    /// assert_eq!("gay = true", config.value());
+    /// ```
+    Memoizer0,
+    "no arguments",
+    ()
+);
+memoizer!(
+    /// Memoizes a function taking two arguments, keyed on a tuple of them. Every argument must be `Eq + Hash + Clone`. Functions taking up to twelve arguments can be memoized with the other numbered memoizers, which all work the same way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer2;
+    /// let mut binomial = Memoizer2::new(|n: u64, k: u64| (1..=k).fold(1, |c, i| c * (n + 1 - i) / i));
+    /// assert_eq!(252, binomial.value(10, 5));
+    /// assert_eq!(252, binomial.value(10, 5));
+    /// assert_eq!(1, binomial.len());
+    /// ```
+    Memoizer2,
+    "two arguments",
+    (A a, B b)
+);
+memoizer!(
+    /// Memoizes a function taking three arguments, keyed on a tuple of them. See [`Memoizer2`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer3;
+    /// let mut cost = Memoizer3::with_capacity_lru(100, |from: u32, to: u32, weight: u32| (to - from) * weight);
+    /// assert_eq!(30, cost.value(1, 4, 10));
+    /// ```
+    Memoizer3,
+    "three arguments",
+    (A a, B b, C c)
+);
+memoizer!(
+    /// Memoizes a function taking four arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer4,
+    "four arguments",
+    (A a, B b, C c, D d)
+);
+memoizer!(
+    /// Memoizes a function taking five arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer5,
+    "five arguments",
+    (A a, B b, C c, D d, E e)
+);
+memoizer!(
+    /// Memoizes a function taking six arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer6,
+    "six arguments",
+    (A a, B b, C c, D d, E e, G g)
+);
+memoizer!(
+    /// Memoizes a function taking seven arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer7,
+    "seven arguments",
+    (A a, B b, C c, D d, E e, G g, H h)
+);
+memoizer!(
+    /// Memoizes a function taking eight arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer8,
+    "eight arguments",
+    (A a, B b, C c, D d, E e, G g, H h, I i)
+);
+memoizer!(
+    /// Memoizes a function taking nine arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer9,
+    "nine arguments",
+    (A a, B b, C c, D d, E e, G g, H h, I i, J j)
+);
+memoizer!(
+    /// Memoizes a function taking ten arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer10,
+    "ten arguments",
+    (A a, B b, C c, D d, E e, G g, H h, I i, J j, K k)
+);
+memoizer!(
+    /// Memoizes a function taking eleven arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer11,
+    "eleven arguments",
+    (A a, B b, C c, D d, E e, G g, H h, I i, J j, K k, L l)
+);
+memoizer!(
+    /// Memoizes a function taking twelve arguments, keyed on a tuple of them. See [`Memoizer2`].
+    Memoizer12,
+    "twelve arguments",
+    (A a, B b, C c, D d, E e, G g, H h, I i, J j, K k, L l, M m)
+);
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::ManualClock;
+
+    /* Each combination of arguments is computed once */
+    #[test]
+    fn arguments() {
+        let calls = Cell::new(0);
+        let mut cost = Memoizer3::new(|from: u32, to: u32, weight: String| {
+            calls.set(calls.get() + 1);
+            (to - from) as usize * weight.len()
+        });
+        assert_eq!(6, cost.value(1, 3, String::from("gay")));
+        assert_eq!(6, cost.value(1, 3, String::from("gay")));
+        assert_eq!(10, cost.value(1, 3, String::from("girls")));
+        assert_eq!(2, calls.get());
+
+        assert_eq!(Some(6), cost.invalidate(1, 3, String::from("gay")));
+        assert_eq!(&6, cost.value_ref(1, 3, String::from("gay")));
+        assert_eq!(3, calls.get());
+
+        let mut sum = Memoizer12::new(|a, b, c, d, e, f, g, h, i, j, k, l| {
+            a + b + c + d + e + f + g + h + i + j + k + l
+        });
+        assert_eq!(78, sum.value(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12));
+    }
+
+    /* The function without arguments is called again once its value expires */
+    #[test]
+    fn no_arguments() {
+        let calls = Cell::new(0);
+        let clock = ManualClock::new();
+        let mut once = Memoizer0::new(|| calls.set(calls.get() + 1))
+            .with_ttl(Duration::from_secs(10))
+            .with_clock(clock.clone());
+        once.value();
+        once.value();
+        assert_eq!(1, calls.get());
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(1, once.purge_expired());
+        once.value();
+        assert_eq!(2, calls.get());
+    }
+
+    /* Arguments are managed spread out, entries are keyed on their tuple */
+    #[test]
+    fn management() {
+        let calls = Cell::new(0);
+        let mut add = Memoizer2::new(|a: u32, b: u32| {
+            calls.set(calls.get() + 1);
+            a + b
+        });
+        assert!(!add.contains(1, 2));
+        assert_eq!(None, add.insert(1, 2, 30));
+        assert!(!add.prime(1, 2, 3));
+        assert_eq!(Some(&30), add.peek(1, 2));
+        assert_eq!(30, add.value(1, 2));
+
+        assert_eq!(&7, add.entry(3, 4).or_compute());
+        assert_eq!(&7, add.entry(3, 4).or_insert(0));
+        assert_eq!(&(5, 6), add.entry(5, 6).key());
+        assert_eq!(1, calls.get());
+
+        let mut keys = add.keys().copied().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(vec![(1, 2), (3, 4)], keys);
+        add.retain(|&(a, _), _| a == 3);
+        assert_eq!(vec![((3, 4), 7)], add.drain().collect::<Vec<_>>());
+        assert!(add.is_empty());
+    }
+
+    /* Expired values are served while they are refreshed in the background */
+    #[test]
+    fn refresh() {
+        use std::sync::{Arc, Mutex};
+
+        let tasks = Arc::new(Mutex::new(Vec::<crate::Task>::new()));
+        let queue = Arc::clone(&tasks);
+        let run = || tasks.lock().unwrap().drain(..).for_each(|task| task());
+        let offset = Arc::new(Mutex::new(0));
+        let current = Arc::clone(&offset);
+        let clock = ManualClock::new();
+        let mut add = Memoizer2::new(move |a: u32, b: u32| a + b + *current.lock().unwrap())
+            .with_ttl(Duration::from_secs(10))
+            .with_stale_while_revalidate(Duration::from_secs(5))
+            .with_refresh_ahead(Duration::from_secs(1))
+            .with_refresher(move |task| queue.lock().unwrap().push(task))
+            .with_clock(clock.clone());
+
+        assert_eq!(3, add.value(1, 2));
+        *offset.lock().unwrap() = 10;
+        clock.advance(Duration::from_secs(12));
+        assert_eq!(3, add.value(1, 2));
+        run();
+        assert_eq!(13, add.value(1, 2));
+
+        *offset.lock().unwrap() = 20;
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(13, add.value(1, 2));
+        run();
+        assert_eq!(23, add.value(1, 2));
+
+        let mut zero = Memoizer0::new(|| 1).with_refresher(|task: crate::Task| task());
+        assert_eq!(1, zero.value());
+    }
+
+    /* Bounded memoizers evict whole argument lists */
+    #[test]
+    fn bounded() {
+        let mut add = Memoizer2::with_capacity_lru(1, |a: u8, b: u8| a + b);
+        assert_eq!(3, add.value(1, 2));
+        assert_eq!(3, add.value(2, 1));
+        assert_eq!(1, add.len());
+        assert_eq!(None, add.invalidate(1, 2));
+
+        let mut none = Memoizer2::with_capacity_lru(0, |a: u8, b: u8| a + b);
+        assert_eq!(&3, none.value_ref(1, 2));
+        assert!(none.is_empty());
+    }
+}
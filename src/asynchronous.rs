@@ -0,0 +1,363 @@
+//! Memoization of asynchronous functions.
+
+// Imports
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// Memoizes an asynchronous function, one returning a future. The value the future resolves to is cached rather than the future itself, and concurrent calls for the same key share a single call to the function.
+///
+/// The memoizer is not tied to any particular executor. Values are looked up from `&self`, so it can be shared between tasks, and between threads as long as the function is `Sync` and the keys and values are `Send`.
+#[derive(Debug)]
+pub struct AsyncMemoizer<U, V, F, Fut>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> Fut,
+    Fut: Future<Output = V>,
+{
+    function: F,
+    map: Mutex<HashMap<U, Slot<V>>>,
+}
+
+impl<U, V, F, Fut> AsyncMemoizer<U, V, F, Fut>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> Fut,
+    Fut: Future<Output = V>,
+{
+    /// Creates a new AsyncMemoizer given a function returning a future, such as an `async` closure or block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::AsyncMemoizer;
+    ///# use std::future::Future;
+    ///# use std::pin::pin;
+    ///# use std::task::{Context, Poll, Waker};
+    ///# fn block_on<T>(future: impl Future<Output = T>) -> T {
+    ///#     let mut future = pin!(future);
+    ///#     let mut cx = Context::from_waker(Waker::noop());
+    ///#     loop {
+    ///#         if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+    ///#             return value;
+    ///#         }
+    ///#     }
+    ///# }
+    /// let add_two = AsyncMemoizer::new(|n: u32| async move { n + 2 });
+    /// assert_eq!(4, block_on(add_two.value(2)));
+    /// ```
+    ///
+    pub fn new(function: F) -> AsyncMemoizer<U, V, F, Fut> {
+        AsyncMemoizer {
+            function,
+            map: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the value for the memoized function. If the function's future has already resolved for `arg` before, it will use the previous value. If another task is currently awaiting the function for `arg`, this waits for that task's value instead of calling the function again. Should that task be cancelled, or its future panic, one of the waiting tasks takes over.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::AsyncMemoizer;
+    ///# use std::future::Future;
+    ///# use std::pin::pin;
+    ///# use std::task::{Context, Poll, Waker};
+    ///# fn block_on<T>(future: impl Future<Output = T>) -> T {
+    ///#     let mut future = pin!(future);
+    ///#     let mut cx = Context::from_waker(Waker::noop());
+    ///#     loop {
+    ///#         if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+    ///#             return value;
+    ///#         }
+    ///#     }
+    ///# }
+    /// async fn length(s: String) -> usize {
+    ///     s.len()
+    /// }
+    ///
+    /// let length = AsyncMemoizer::new(length);
+    /// block_on(async {
+    ///     assert_eq!(8, length.value(String::from("gaygirls")).await);
+    ///     assert_eq!(8, length.value(String::from("gaygirls")).await);
+    /// });
+    /// ```
+    ///
+    pub async fn value(&self, arg: U) -> V {
+        loop {
+            let flight = {
+                let mut map = lock(&self.map);
+                match map.get(&arg) {
+                    Some(Slot::Ready(value)) => return value.clone(),
+                    Some(Slot::Pending(flight)) => Some(Arc::clone(flight)),
+                    None => {
+                        let flight = Arc::new(Mutex::new(FlightState::Computing(Vec::new())));
+                        map.insert(arg.clone(), Slot::Pending(flight));
+                        None
+                    }
+                }
+            };
+
+            if let Some(flight) = flight {
+                match (Wait { flight: &flight }).await {
+                    Some(value) => return value,
+                    // The task computing it went away, try again
+                    None => continue,
+                }
+            }
+
+            let mut guard = Landing {
+                map: &self.map,
+                key: &arg,
+                value: None,
+            };
+            let value = (self.function)(arg.clone()).await;
+            guard.value = Some(value.clone());
+            return value;
+        }
+    }
+}
+
+/* A resolved value, or one which some task is still awaiting */
+#[derive(Debug)]
+enum Slot<V> {
+    Ready(V),
+    Pending(Arc<Mutex<FlightState<V>>>),
+}
+
+#[derive(Debug)]
+enum FlightState<V> {
+    Computing(Vec<Waker>),
+    Done(V),
+    Abandoned,
+}
+
+/* Resolves once the task computing a value is done, None if it went away */
+struct Wait<'a, V> {
+    flight: &'a Mutex<FlightState<V>>,
+}
+
+impl<'a, V: Clone> Future for Wait<'a, V> {
+    type Output = Option<V>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<V>> {
+        match &mut *lock(self.flight) {
+            FlightState::Computing(wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            FlightState::Done(value) => Poll::Ready(Some(value.clone())),
+            FlightState::Abandoned => Poll::Ready(None),
+        }
+    }
+}
+
+/* Held by the task awaiting the function. Caches the value and wakes the
+ * waiting tasks when dropped, or lets them take over if the task was
+ * cancelled or panicked before the value was set.
+ */
+struct Landing<'a, U, V>
+where
+    U: Eq + Hash,
+    V: Clone,
+{
+    map: &'a Mutex<HashMap<U, Slot<V>>>,
+    key: &'a U,
+    value: Option<V>,
+}
+
+impl<'a, U, V> Drop for Landing<'a, U, V>
+where
+    U: Eq + Hash,
+    V: Clone,
+{
+    fn drop(&mut self) {
+        let value = self.value.take();
+        let slot = {
+            let mut map = lock(self.map);
+            match &value {
+                Some(value) => map
+                    .get_mut(self.key)
+                    .map(|slot| mem::replace(slot, Slot::Ready(value.clone()))),
+                None => map.remove(self.key),
+            }
+        };
+
+        if let Some(Slot::Pending(flight)) = slot {
+            let landed = match value {
+                Some(value) => FlightState::Done(value),
+                None => FlightState::Abandoned,
+            };
+            if let FlightState::Computing(wakers) = mem::replace(&mut *lock(&flight), landed) {
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        }
+    }
+}
+
+/* None of the locks guard invariants a panic could break */
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::pin::pin;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    /* Minimal executor, parks the thread until the future is woken */
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn poll_once<T>(future: Pin<&mut impl Future<Output = T>>) -> Poll<T> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    /* Stays pending until the test opens it */
+    struct Gate(Rc<Cell<bool>>);
+
+    impl Future for Gate {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+            if self.0.get() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    /* Resolved values are cached, not the futures */
+    #[test]
+    fn memoization() {
+        let calls = AtomicUsize::new(0);
+        let add_two = AsyncMemoizer::new(|n: u32| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move { n + 2 }
+        });
+
+        block_on(async {
+            assert_eq!(4, add_two.value(2).await);
+            assert_eq!(4, add_two.value(2).await);
+            assert_eq!(5, add_two.value(3).await);
+        });
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    /* Concurrent awaits of the same key share one call to the function */
+    #[test]
+    fn deduplication() {
+        let calls = Cell::new(0);
+        let open = Rc::new(Cell::new(false));
+        let slow = AsyncMemoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            let gate = Gate(Rc::clone(&open));
+            async move {
+                gate.await;
+                n * 2
+            }
+        });
+
+        let mut first = pin!(slow.value(21));
+        let mut second = pin!(slow.value(21));
+        assert_eq!(Poll::Pending, poll_once(first.as_mut()));
+        assert_eq!(Poll::Pending, poll_once(second.as_mut()));
+
+        open.set(true);
+        assert_eq!(Poll::Pending, poll_once(second.as_mut()));
+        assert_eq!(Poll::Ready(42), poll_once(first.as_mut()));
+        assert_eq!(Poll::Ready(42), poll_once(second.as_mut()));
+        assert_eq!(1, calls.get());
+    }
+
+    /* If the task computing a value is dropped a waiting task takes over */
+    #[test]
+    fn cancellation() {
+        let calls = Cell::new(0);
+        let open = Rc::new(Cell::new(false));
+        let slow = AsyncMemoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            let gate = Gate(Rc::clone(&open));
+            async move {
+                gate.await;
+                n * 2
+            }
+        });
+
+        let mut second = pin!(slow.value(21));
+        {
+            let mut first = Box::pin(slow.value(21));
+            assert_eq!(Poll::Pending, poll_once(first.as_mut()));
+            assert_eq!(Poll::Pending, poll_once(second.as_mut()));
+        }
+
+        open.set(true);
+        assert_eq!(Poll::Ready(42), poll_once(second.as_mut()));
+        assert_eq!(2, calls.get());
+        assert_eq!(42, block_on(slow.value(21)));
+        assert_eq!(2, calls.get());
+    }
+
+    /* Waiting tasks on other threads are woken when the value is ready */
+    #[test]
+    fn shared_between_threads() {
+        let calls = AtomicUsize::new(0);
+        let square = AsyncMemoizer::new(|n: u64| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                thread::sleep(std::time::Duration::from_millis(20));
+                n * n
+            }
+        });
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| assert_eq!(49, block_on(square.value(7))));
+            }
+        });
+        assert_eq!(49, block_on(square.value(7)));
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    /* Multi-threaded executors need the futures to be Send */
+    #[test]
+    fn send_futures() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let length = AsyncMemoizer::new(|s: String| async move { s.len() });
+        let future = length.value(String::from("gay"));
+        assert_send(&future);
+        assert_eq!(3, block_on(future));
+    }
+}
@@ -0,0 +1,100 @@
+//! Sources of the current time, used to expire memoized values.
+
+// Imports
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::Sleep;
+
+/// A source of the current time. Memoizers use the [`SystemClock`] unless given another one, tests can use a [`ManualClock`] to control when values expire without sleeping.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time. Successive calls should never go backwards.
+    fn now(&self) -> Instant;
+}
+
+/// The operating system's monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when told to. Clones share the same time, so a test can hand one to a memoizer and keep another to advance it. Used as a [`Sleep`](crate::Sleep), sleeping on it advances it straight away.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::{ManualClock, Memoizer};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// let mut add_two = Memoizer::new(|n| n + 2)
+///     .with_ttl(Duration::from_secs(60))
+///     .with_clock(clock.clone());
+///
+/// add_two.value(2);
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(1, add_two.purge_expired());
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    /// Creates a new clock, stopped at the current time.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock, and every clone of it, forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Sleep for ManualClock {
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Clones move together */
+    #[test]
+    fn manual() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let copy = clock.clone();
+        copy.advance(Duration::from_secs(5));
+        assert_eq!(start + Duration::from_secs(5), clock.now());
+        assert_eq!(clock.now(), copy.now());
+
+        copy.sleep(Duration::from_secs(1));
+        assert_eq!(start + Duration::from_secs(6), clock.now());
+    }
+}
@@ -0,0 +1,360 @@
+//! A view into a single key of a memoizer.
+
+// Imports
+use std::fmt;
+use std::hash::Hash;
+
+use crate::policy::{Lru, Policy};
+use crate::store::Store;
+
+/// A view into a single key of a [`Memoizer`](crate::Memoizer), which may or may not have a value cached. Created by [`Memoizer::entry`](crate::Memoizer::entry), or by the `entry` method of the numbered memoizers such as [`Memoizer2::entry`](crate::Memoizer2::entry), whose key is the tuple of arguments.
+///
+/// Values are only ever handed out as shared references, since changing one would leave its time to live and estimated size out of date. Use [`OccupiedEntry::replace`] to change a cached value instead.
+pub enum Entry<'a, U, V, F, P = Lru<U>>
+where
+    U: Eq + Hash + Clone,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    /// A key with a value cached which hasn't expired.
+    Occupied(OccupiedEntry<'a, U, V, P>),
+    /// A key without a value cached.
+    Vacant(VacantEntry<'a, U, V, F, P>),
+}
+
+/// A key with a value cached which hasn't expired. Part of the [`Entry`] enum.
+pub struct OccupiedEntry<'a, U, V, P = Lru<U>>
+where
+    U: Eq + Hash + Clone,
+    P: Policy<U>,
+{
+    store: &'a mut Store<U, V, P>,
+    key: U,
+}
+
+/// A key without a value cached. Part of the [`Entry`] enum.
+pub struct VacantEntry<'a, U, V, F, P = Lru<U>>
+where
+    U: Eq + Hash + Clone,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    store: &'a mut Store<U, V, P>,
+    key: U,
+    function: F,
+}
+
+impl<'a, U, V, F, P> Entry<'a, U, V, F, P>
+where
+    U: Eq + Hash + Clone,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    pub(crate) fn new(store: &'a mut Store<U, V, P>, key: U, function: F) -> Entry<'a, U, V, F, P> {
+        if store.cached(&key) {
+            Entry::Occupied(OccupiedEntry { store, key })
+        } else {
+            Entry::Vacant(VacantEntry {
+                store,
+                key,
+                function,
+            })
+        }
+    }
+
+    /// Returns the entry's key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(&2, add_two.entry(2).key());
+    /// ```
+    ///
+    pub fn key(&self) -> &U {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the cached value, calling the memoizer's function to compute it if there isn't one. The same as [`Memoizer::value_ref`](crate::Memoizer::value_ref).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(&4, add_two.entry(2).or_compute());
+    /// ```
+    ///
+    pub fn or_compute(self) -> &'a V {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => entry.compute(),
+        }
+    }
+
+    /// Returns the cached value, caching `value` if there isn't one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(&5, add_two.entry(2).or_insert(5));
+    /// assert_eq!(&5, add_two.entry(2).or_insert(4));
+    /// ```
+    ///
+    pub fn or_insert(self, value: V) -> &'a V {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Returns the cached value, caching the one returned by `default` if there isn't one. Useful for computing a value differently than the memoizer's function would, for example with extra context, while still caching it under the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let offset = 10;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(&14, add_two.entry(2).or_insert_with(|&n| n + 2 + offset));
+    /// ```
+    ///
+    pub fn or_insert_with<D>(self, default: D) -> &'a V
+    where
+        D: FnOnce(&U) -> V,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+}
+
+impl<'a, U, V, P> OccupiedEntry<'a, U, V, P>
+where
+    U: Eq + Hash + Clone,
+    P: Policy<U>,
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &U {
+        &self.key
+    }
+
+    /// Returns the cached value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// if let Entry::Occupied(entry) = add_two.entry(2) {
+    ///     assert_eq!(&4, entry.get());
+    /// }
+    /// ```
+    ///
+    pub fn get(&self) -> &V {
+        &self.store.map[&self.key].value
+    }
+
+    /// Returns the cached value, with a reference which lasts as long as the memoizer's borrow.
+    pub fn into_ref(self) -> &'a V {
+        &self.store.map[&self.key].value
+    }
+
+    /// Replaces the cached value, returning the old one. The new value counts as freshly computed, so its time to live starts over.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// if let Entry::Occupied(entry) = add_two.entry(2) {
+    ///     assert_eq!(4, entry.replace(5));
+    /// }
+    /// assert_eq!(5, add_two.value(2));
+    /// ```
+    ///
+    pub fn replace(self, value: V) -> V {
+        self.store
+            .insert(self.key, value)
+            .expect("occupied entry has a value")
+    }
+
+    /// Removes the cached value and returns it, so that it is computed again the next time it is asked for.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// if let Entry::Occupied(entry) = add_two.entry(2) {
+    ///     assert_eq!(4, entry.remove());
+    /// }
+    /// assert!(add_two.is_empty());
+    /// ```
+    ///
+    pub fn remove(self) -> V {
+        self.store
+            .invalidate(&self.key)
+            .expect("occupied entry has a value")
+    }
+}
+
+impl<'a, U, V, F, P> VacantEntry<'a, U, V, F, P>
+where
+    U: Eq + Hash + Clone,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &U {
+        &self.key
+    }
+
+    /// Returns the entry's key, without caching anything.
+    pub fn into_key(self) -> U {
+        self.key
+    }
+
+    /// Calls the memoizer's function to compute the value, caches it and returns it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// if let Entry::Vacant(entry) = add_two.entry(2) {
+    ///     assert_eq!(&4, entry.compute());
+    /// }
+    /// ```
+    ///
+    pub fn compute(self) -> &'a V {
+        self.store.compute(self.key, self.function)
+    }
+
+    /// Caches a value computed elsewhere and returns it. It expires and counts towards the capacity like a value computed by the memoizer's function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// if let Entry::Vacant(entry) = add_two.entry(2) {
+    ///     assert_eq!(&5, entry.insert(5));
+    /// }
+    /// assert_eq!(5, add_two.value(2));
+    /// ```
+    ///
+    pub fn insert(self, value: V) -> &'a V {
+        self.store.cache(self.key, value, None)
+    }
+}
+
+impl<'a, U, V, F, P> fmt::Debug for Entry<'a, U, V, F, P>
+where
+    U: Eq + Hash + Clone + fmt::Debug,
+    V: fmt::Debug,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<'a, U, V, P> fmt::Debug for OccupiedEntry<'a, U, V, P>
+where
+    U: Eq + Hash + Clone + fmt::Debug,
+    V: fmt::Debug,
+    P: Policy<U>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<'a, U, V, F, P> fmt::Debug for VacantEntry<'a, U, V, F, P>
+where
+    U: Eq + Hash + Clone + fmt::Debug,
+    F: FnOnce(U) -> V,
+    P: Policy<U>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(self.key()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::Memoizer;
+
+    /* Vacant entries are filled by the function or by hand, and occupied ones
+     * can be replaced or removed.
+     */
+    #[test]
+    fn entries() {
+        let calls = Cell::new(0);
+        let mut add_two = Memoizer::with_capacity_lru(2, |n| {
+            calls.set(calls.get() + 1);
+            n + 2
+        });
+        assert!(matches!(add_two.entry(1), Entry::Vacant(_)));
+        assert_eq!(&3, add_two.entry(1).or_compute());
+        assert_eq!(&3, add_two.entry(1).or_insert(0));
+        assert_eq!(1, calls.get());
+
+        match add_two.entry(2) {
+            Entry::Vacant(entry) => assert_eq!(&20, entry.insert(20)),
+            Entry::Occupied(_) => panic!("2 isn't cached"),
+        }
+        match add_two.entry(2) {
+            Entry::Occupied(entry) => assert_eq!(20, entry.replace(4)),
+            Entry::Vacant(_) => panic!("2 is cached"),
+        }
+        assert_eq!(4, add_two.value(2));
+
+        // Inserted values are bounded like computed ones
+        add_two.entry(3).or_insert_with(|&n| n * 10);
+        assert!(!add_two.contains(&1));
+        assert_eq!(Some(&30), add_two.peek(&3));
+
+        match add_two.entry(3) {
+            Entry::Occupied(entry) => assert_eq!(30, entry.remove()),
+            Entry::Vacant(_) => panic!("3 is cached"),
+        }
+        add_two.value(4);
+        add_two.value(5);
+        assert!(!add_two.contains(&2));
+        assert_eq!(3, calls.get());
+    }
+
+    /* Values inserted into a memoizer without room are still returned */
+    #[test]
+    fn zero_capacity() {
+        let mut add_two = Memoizer::with_capacity_lru(0, |n| n + 2);
+        assert_eq!(&5, add_two.entry(2).or_insert(5));
+        assert!(add_two.is_empty());
+        assert_eq!("Entry(VacantEntry(2))", format!("{:?}", add_two.entry(2)));
+    }
+}
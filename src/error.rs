@@ -0,0 +1,68 @@
+//! Errors reported by the memoizers.
+
+// Imports
+use std::error::Error;
+use std::fmt;
+
+/// Error returned when a recursive memoized function asks for the value of a key which is still being computed, i.e. the function depends on itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError<U> {
+    chain: Vec<U>,
+}
+
+impl<U> CycleError<U> {
+    pub(crate) fn new(chain: Vec<U>) -> CycleError<U> {
+        CycleError { chain }
+    }
+
+    /// The chain of dependencies that forms the cycle. The first and last keys are the same, each key in between was requested while computing the one before it.
+    pub fn chain(&self) -> &[U] {
+        &self.chain
+    }
+
+    /// Consumes the error, returning the chain of dependencies that forms the cycle.
+    pub fn into_chain(self) -> Vec<U> {
+        self.chain
+    }
+
+    /* Panics on behalf of the memoizers' value methods. Their keys needn't be
+     * Debug, so only the length of the cycle can be given.
+     */
+    pub(crate) fn panic(&self) -> ! {
+        panic!(
+            "memoized function depends on itself through a cycle of {} keys, use try_value to get the chain of keys",
+            self.chain.len() - 1
+        )
+    }
+}
+
+impl<U: fmt::Debug> fmt::Display for CycleError<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memoized function depends on itself: ")?;
+        for (i, key) in self.chain.iter().enumerate() {
+            if i > 0 {
+                write!(f, " -> ")?;
+            }
+            write!(f, "{:?}", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl<U: fmt::Debug> Error for CycleError<U> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* The chain is printed using each key's Debug form */
+    #[test]
+    fn display() {
+        let error = CycleError::new(vec!["a", "b", "a"]);
+        assert_eq!(
+            "memoized function depends on itself: \"a\" -> \"b\" -> \"a\"",
+            error.to_string()
+        );
+        assert_eq!(vec!["a", "b", "a"], error.into_chain());
+    }
+}
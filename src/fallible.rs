@@ -0,0 +1,698 @@
+//! Memoization of functions which can fail.
+
+// Imports
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::policy::{Lru, Policy};
+use crate::store::Store;
+use crate::Clock;
+
+/// Memoizes a function returning a `Result`. Successful values are cached like [`Memoizer`](crate::Memoizer) does, and can be bounded by a capacity and given a time to live the same way. Errors are returned to the caller and by default not cached at all, so the next call for the same key tries again.
+///
+/// Errors can also be cached for a while with [`TryMemoizer::with_error_ttl`], so that a key which keeps failing isn't retried on every call. Or the function can be retried straight away with [`TryMemoizer::with_retry`], backing off between attempts.
+#[derive(Debug)]
+pub struct TryMemoizer<U, V, E, F, P = Lru<U>>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> Result<V, E>,
+    P: Policy<U>,
+{
+    function: F,
+    store: Store<U, V, P>,
+    errors: HashMap<U, (E, Instant)>,
+    error_ttl: Option<Duration>,
+    clone_error: Option<fn(&E) -> E>,
+    backoff: Option<Backoff>,
+    failures: HashMap<U, (usize, Instant)>,
+    sleeper: Box<dyn Sleep>,
+}
+
+/// How many times to try a failing function and how long to wait in between. Each wait is longer than the last by a constant factor, up to a maximum.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::Backoff;
+/// use std::time::Duration;
+///
+/// // Waits 100ms, 300ms, 900ms then 1s
+/// let backoff = Backoff::new(5, Duration::from_millis(100))
+///     .with_multiplier(3)
+///     .with_max_delay(Duration::from_secs(1));
+/// assert_eq!(Duration::from_millis(900), backoff.delay(2));
+/// assert_eq!(Duration::from_secs(1), backoff.delay(3));
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    attempts: u32,
+    delay: Duration,
+    multiplier: u32,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    /// Tries the function up to `attempts` times in total, waiting `delay` after the first failure and twice as long after each one after that.
+    pub fn new(attempts: u32, delay: Duration) -> Backoff {
+        Backoff {
+            attempts,
+            delay,
+            multiplier: 2,
+            max_delay: Duration::MAX,
+        }
+    }
+
+    /// Sets the factor each wait grows by, 2 unless told otherwise.
+    pub fn with_multiplier(mut self, multiplier: u32) -> Backoff {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the longest a single wait may be.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Backoff {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns how long to wait after the failure of attempt `retry`, counting from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.multiplier
+            .checked_pow(retry)
+            .and_then(|factor| self.delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Waits between retries of a failing function. Any `Fn(Duration)` can be used, [`TryMemoizer`] uses [`thread::sleep`] unless given another one, and a [`ManualClock`](crate::ManualClock) just advances.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::{Backoff, TryMemoizer};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// let waits = Arc::new(Mutex::new(Vec::new()));
+/// let log = Arc::clone(&waits);
+/// let mut down = TryMemoizer::new(|_: u32| Err::<u32, _>("down"))
+///     .with_retry(Backoff::new(3, Duration::from_secs(1)))
+///     .with_sleeper(move |wait| log.lock().unwrap().push(wait));
+///
+/// assert_eq!(Err("down"), down.value(0));
+/// assert_eq!(vec![Duration::from_secs(1), Duration::from_secs(2)], *waits.lock().unwrap());
+/// ```
+///
+pub trait Sleep: Send + Sync {
+    /// Waits for `duration` to pass.
+    fn sleep(&self, duration: Duration);
+}
+
+impl<T> Sleep for T
+where
+    T: Fn(Duration) + Send + Sync,
+{
+    fn sleep(&self, duration: Duration) {
+        self(duration)
+    }
+}
+
+impl fmt::Debug for dyn Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sleep")
+    }
+}
+
+impl<U, V, E, F> TryMemoizer<U, V, E, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> Result<V, E>,
+{
+    /// Creates a new TryMemoizer given a function returning a `Result`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::TryMemoizer;
+    /// let mut parse = TryMemoizer::new(|s: &str| s.parse::<u32>());
+    /// assert_eq!(Ok(42), parse.value("42"));
+    /// assert!(parse.value("gay").is_err());
+    /// ```
+    ///
+    pub fn new(function: F) -> TryMemoizer<U, V, E, F> {
+        TryMemoizer::with_store(Store::new(None, Lru::new()), function)
+    }
+
+    /// Creates a new TryMemoizer given a function returning a `Result`, which holds on to at most `capacity` successful values by evicting the least recently used one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::TryMemoizer;
+    /// let mut parse = TryMemoizer::with_capacity_lru(1, |s: &str| s.parse::<u32>());
+    /// assert_eq!(Ok(42), parse.value("42"));
+    /// assert_eq!(Ok(7), parse.value("7"));
+    /// assert_eq!(1, parse.len());
+    /// ```
+    ///
+    pub fn with_capacity_lru(capacity: usize, function: F) -> TryMemoizer<U, V, E, F> {
+        TryMemoizer::with_policy(capacity, Lru::new(), function)
+    }
+}
+
+impl<U, V, E, F, P> TryMemoizer<U, V, E, F, P>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> Result<V, E>,
+    P: Policy<U>,
+{
+    /// Creates a new TryMemoizer given a function returning a `Result`, which holds on to at most `capacity` successful values by evicting the ones chosen by `policy`. See [`Memoizer::with_policy`](crate::Memoizer::with_policy).
+    pub fn with_policy(capacity: usize, policy: P, function: F) -> TryMemoizer<U, V, E, F, P> {
+        TryMemoizer::with_store(Store::new(Some(capacity), policy), function)
+    }
+
+    fn with_store(store: Store<U, V, P>, function: F) -> TryMemoizer<U, V, E, F, P> {
+        TryMemoizer {
+            function,
+            store,
+            errors: HashMap::new(),
+            error_ttl: None,
+            clone_error: None,
+            backoff: None,
+            failures: HashMap::new(),
+            sleeper: Box::new(thread::sleep),
+        }
+    }
+
+    /// Sets how long successful values are kept after being computed. Errors are only cached if given their own, usually shorter, time to live with [`TryMemoizer::with_error_ttl`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, TryMemoizer};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let mut parse = TryMemoizer::new(|s: &str| s.parse::<u32>())
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_clock(clock.clone());
+    ///
+    /// assert_eq!(Ok(42), parse.value("42"));
+    /// clock.advance(Duration::from_secs(60));
+    /// assert_eq!(1, parse.purge_expired());
+    /// ```
+    ///
+    pub fn with_ttl(mut self, ttl: Duration) -> TryMemoizer<U, V, E, F, P> {
+        self.store = self.store.with_ttl(ttl);
+        self
+    }
+
+    /// Sets a function deciding how long each successful value is kept. See [`Memoizer::with_expiry`](crate::Memoizer::with_expiry).
+    pub fn with_expiry(
+        mut self,
+        expiry: fn(&U, &V) -> Option<Duration>,
+    ) -> TryMemoizer<U, V, E, F, P> {
+        self.store = self.store.with_expiry(expiry);
+        self
+    }
+
+    /// Sets the clock used to decide when values and cached errors expire, instead of the system clock. Useful for testing with a [`ManualClock`](crate::ManualClock).
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> TryMemoizer<U, V, E, F, P> {
+        self.store = self.store.with_clock(clock);
+        self
+    }
+
+    /// Retries the function when it fails, as many times as `backoff` allows, before returning the last error. Waits are done with [`thread::sleep`] unless the memoizer is given another [`Sleep`] with [`TryMemoizer::with_sleeper`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Backoff, TryMemoizer};
+    /// use std::cell::Cell;
+    /// use std::time::Duration;
+    ///
+    /// // Fails three times before it works
+    /// let attempts = Cell::new(0);
+    /// let mut flaky = TryMemoizer::new(|n: u32| {
+    ///     attempts.set(attempts.get() + 1);
+    ///     if attempts.get() < 4 {
+    ///         Err("not yet")
+    ///     } else {
+    ///         Ok(n + 2)
+    ///     }
+    /// })
+    /// .with_retry(Backoff::new(3, Duration::from_millis(1)));
+    ///
+    /// assert_eq!(Err("not yet"), flaky.value(2));
+    /// assert_eq!(3, flaky.failures(&2));
+    ///
+    /// // Works on the next try, which clears the count
+    /// assert_eq!(Ok(4), flaky.value(2));
+    /// assert_eq!(0, flaky.failures(&2));
+    /// ```
+    ///
+    pub fn with_retry(mut self, backoff: Backoff) -> TryMemoizer<U, V, E, F, P> {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Sets how to wait between retries, instead of putting the thread to sleep. Useful for testing with a [`ManualClock`](crate::ManualClock), which advances instead of waiting.
+    pub fn with_sleeper<S: Sleep + 'static>(mut self, sleeper: S) -> TryMemoizer<U, V, E, F, P> {
+        self.sleeper = Box::new(sleeper);
+        self
+    }
+
+    /// Returns how many times the function has failed for `arg` since it last succeeded, counting every retry. A bounded memoizer keeps counts for at most as many keys as values, forgetting the key which failed longest ago to make room.
+    pub fn failures(&self, arg: &U) -> usize {
+        self.failures.get(arg).map_or(0, |&(failures, _)| failures)
+    }
+
+    /// Forgets how many times the function has failed, for every key.
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+
+    /// Returns the maximum number of successful values the memoizer holds on to, or None if it is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.store.capacity()
+    }
+
+    /// Returns the value for the memoized function. Successful values are cached and returned from then on, until they expire or are evicted. Errors are returned as they are, after retrying if the memoizer was given a [`Backoff`], and only cached if the memoizer was given an error time to live, in which case the same error is returned until it expires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::TryMemoizer;
+    /// use std::cell::Cell;
+    ///
+    /// // Fails the first time, works from then on
+    /// let attempts = Cell::new(0);
+    /// let mut flaky = TryMemoizer::new(|n: u32| {
+    ///     attempts.set(attempts.get() + 1);
+    ///     if attempts.get() == 1 {
+    ///         Err("not yet")
+    ///     } else {
+    ///         Ok(n + 2)
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(Err("not yet"), flaky.value(2));
+    /// assert_eq!(Ok(4), flaky.value(2));
+    /// assert_eq!(Ok(4), flaky.value(2));
+    /// assert_eq!(2, attempts.get());
+    /// ```
+    ///
+    pub fn value(&mut self, arg: U) -> Result<V, E> {
+        if let Some(value) = self.store.get(&arg) {
+            return Ok(value.clone());
+        }
+
+        if let Some((error, expires)) = self.errors.get(&arg) {
+            if let Some(clone) = self.clone_error {
+                if self.store.clock.now() < *expires {
+                    return Err(clone(error));
+                }
+            }
+            self.errors.remove(&arg);
+        }
+
+        let attempts = self.backoff.map_or(1, |backoff| backoff.attempts.max(1));
+        let mut retry = 0;
+        let error = loop {
+            let (result, delta) = self.store.time(|| (self.function)(arg.clone()));
+            match result {
+                Ok(value) => {
+                    self.failures.remove(&arg);
+                    return Ok(self.store.cache(arg, value, delta).clone());
+                }
+                Err(error) => {
+                    self.count_failure(&arg);
+                    retry += 1;
+                    match self.backoff {
+                        Some(backoff) if retry < attempts => {
+                            self.sleeper.sleep(backoff.delay(retry - 1))
+                        }
+                        _ => break error,
+                    }
+                }
+            }
+        };
+
+        if let (Some(ttl), Some(clone)) = (self.error_ttl, self.clone_error) {
+            let now = self.store.clock.now();
+            if let Some(expires) = now.checked_add(ttl) {
+                self.cache_error(arg, clone(&error), now, expires);
+            }
+        }
+        Err(error)
+    }
+
+    /* Counts a failure for the key. A bounded memoizer makes room for a new
+     * key by forgetting the one which failed longest ago.
+     */
+    fn count_failure(&mut self, arg: &U) {
+        let now = self.store.clock.now();
+        let capacity = self.store.capacity().unwrap_or(usize::MAX);
+        if self.failures.len() >= capacity && !self.failures.contains_key(arg) {
+            let oldest = self
+                .failures
+                .iter()
+                .min_by_key(|(_, (_, last))| *last)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                self.failures.remove(&key);
+            }
+        }
+        if capacity > 0 {
+            let count = self.failures.entry(arg.clone()).or_insert((0, now));
+            *count = (count.0 + 1, now);
+        }
+    }
+
+    /* Caches an error after dropping the expired ones. A bounded memoizer
+     * holds on to as many errors as values, making room by dropping the
+     * error closest to expiring.
+     */
+    fn cache_error(&mut self, arg: U, error: E, now: Instant, expires: Instant) {
+        self.errors.retain(|_, (_, expires)| now < *expires);
+        let capacity = self.store.capacity().unwrap_or(usize::MAX);
+        if capacity == 0 {
+            return;
+        }
+        if self.errors.len() >= capacity && !self.errors.contains_key(&arg) {
+            let soonest = self
+                .errors
+                .iter()
+                .min_by_key(|(_, (_, expires))| *expires)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = soonest {
+                self.errors.remove(&key);
+            }
+        }
+        self.errors.insert(arg, (error, expires));
+    }
+
+    /// Removes every successful value and cached error which has expired, returning how many there were. Expired ones are otherwise only removed when they are asked for again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, TryMemoizer};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let mut parse = TryMemoizer::new(|s: &str| s.parse::<u32>())
+    ///     .with_error_ttl(Duration::from_secs(5))
+    ///     .with_clock(clock.clone());
+    ///
+    /// assert!(parse.value("gay").is_err());
+    /// clock.advance(Duration::from_secs(5));
+    /// assert_eq!(1, parse.purge_expired());
+    /// ```
+    ///
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.store.clock.now();
+        let errors = self.errors.len();
+        self.errors.retain(|_, (_, expires)| now < *expires);
+        errors - self.errors.len() + self.store.purge_expired()
+    }
+
+    /// Removes the value, cached error and count of failures for the key, returning the value if one was cached, so that the function is called again the next time it is asked for.
+    pub fn invalidate<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.errors.remove(key);
+        self.failures.remove(key);
+        self.store.invalidate(key)
+    }
+
+    /// Removes every cached value and error, and the counts of failures.
+    pub fn clear(&mut self) {
+        self.errors.clear();
+        self.failures.clear();
+        self.store.clear();
+    }
+
+    /// Returns the number of cached successful values, including expired ones which haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns whether there are no cached successful values, including expired ones which haven't been removed yet.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+}
+
+impl<U, V, E, F, P> TryMemoizer<U, V, E, F, P>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    E: Clone,
+    F: Fn(U) -> Result<V, E>,
+    P: Policy<U>,
+{
+    /// Caches errors for `ttl`, usually shorter than values are worth keeping, so that a failing key is only retried once the error has expired. A memoizer with a capacity caches at most that many errors as well, and expired errors are dropped whenever a new one is cached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, TryMemoizer};
+    /// use std::cell::Cell;
+    /// use std::time::Duration;
+    ///
+    /// let calls = Cell::new(0);
+    /// let clock = ManualClock::new();
+    /// let mut lookup = TryMemoizer::new(|_: u32| {
+    ///     calls.set(calls.get() + 1);
+    ///     Err::<u32, _>("down")
+    /// })
+    /// .with_error_ttl(Duration::from_secs(5))
+    /// .with_clock(clock.clone());
+    ///
+    /// assert_eq!(Err("down"), lookup.value(1));
+    /// assert_eq!(Err("down"), lookup.value(1));
+    /// assert_eq!(1, calls.get());
+    ///
+    /// clock.advance(Duration::from_secs(5));
+    /// assert_eq!(Err("down"), lookup.value(1));
+    /// assert_eq!(2, calls.get());
+    /// ```
+    ///
+    pub fn with_error_ttl(mut self, ttl: Duration) -> TryMemoizer<U, V, E, F, P> {
+        self.error_ttl = Some(ttl);
+        self.clone_error = Some(E::clone);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use std::cell::Cell;
+    use std::io;
+
+    /* Successes are cached, errors are retried */
+    #[test]
+    fn errors_not_cached() {
+        let calls = Cell::new(0);
+        let mut half = TryMemoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            match n & 1 {
+                0 => Ok(n / 2),
+                _ => Err(n),
+            }
+        });
+
+        assert_eq!(Ok(2), half.value(4));
+        assert_eq!(Ok(2), half.value(4));
+        assert_eq!(Err(3), half.value(3));
+        assert_eq!(Err(3), half.value(3));
+        assert_eq!(3, calls.get());
+        assert!(half.errors.is_empty());
+    }
+
+    /* Errors which can't be cloned work as long as they aren't cached */
+    #[test]
+    fn uncloneable_errors() {
+        let mut open = TryMemoizer::new(|path: &str| match path {
+            "" => Err(io::Error::new(io::ErrorKind::NotFound, "no path")),
+            path => Ok(path.len()),
+        });
+        assert_eq!(3, open.value("gay").unwrap());
+        assert_eq!(io::ErrorKind::NotFound, open.value("").unwrap_err().kind());
+    }
+
+    /* Successes are bounded and expire, expired errors can be purged */
+    #[test]
+    fn bounded_with_ttl() {
+        let clock = ManualClock::new();
+        let mut half = TryMemoizer::with_capacity_lru(2, |n: u32| match n & 1 {
+            0 => Ok(n / 2),
+            _ => Err(n),
+        })
+        .with_ttl(Duration::from_secs(60))
+        .with_error_ttl(Duration::from_secs(5))
+        .with_clock(clock.clone());
+
+        assert_eq!(Ok(1), half.value(2));
+        assert_eq!(Ok(2), half.value(4));
+        assert_eq!(Ok(3), half.value(6));
+        assert_eq!(2, half.len());
+        assert_eq!(Some(2), half.capacity());
+        assert_eq!(Err(1), half.value(1));
+        assert_eq!(Err(3), half.value(3));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(2, half.purge_expired());
+        assert!(half.errors.is_empty());
+        clock.advance(Duration::from_secs(55));
+        assert_eq!(2, half.purge_expired());
+        assert!(half.is_empty());
+
+        assert_eq!(Ok(1), half.value(2));
+        assert_eq!(Some(1), half.invalidate(&2));
+        half.value(1).unwrap_err();
+        half.clear();
+        assert!(half.errors.is_empty());
+    }
+
+    /* Distinct failing keys can't grow a bounded memoizer's errors */
+    #[test]
+    fn bounded_errors() {
+        let clock = ManualClock::new();
+        let mut fail = TryMemoizer::with_capacity_lru(3, |n: u32| Err::<u32, _>(n))
+            .with_error_ttl(Duration::from_secs(5))
+            .with_clock(clock.clone());
+
+        for n in 0..100 {
+            assert_eq!(Err(n), fail.value(n));
+            clock.advance(Duration::from_millis(10));
+        }
+        assert_eq!(3, fail.errors.len());
+        assert!(fail.errors.contains_key(&99));
+        assert!(!fail.errors.contains_key(&96));
+
+        // Expired errors go as soon as another one is cached
+        clock.advance(Duration::from_secs(5));
+        fail.value(100).unwrap_err();
+        assert_eq!(1, fail.errors.len());
+
+        let mut none = TryMemoizer::with_capacity_lru(0, |n: u32| Err::<u32, _>(n))
+            .with_error_ttl(Duration::from_secs(5));
+        none.value(1).unwrap_err();
+        assert!(none.errors.is_empty());
+    }
+
+    /* Waits grow between attempts, the last error is returned */
+    #[test]
+    fn retry() {
+        let calls = Cell::new(0);
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut down = TryMemoizer::new(|_: u32| {
+            calls.set(calls.get() + 1);
+            Err::<u32, _>(calls.get())
+        })
+        .with_retry(Backoff::new(4, Duration::from_secs(1)).with_max_delay(Duration::from_secs(3)))
+        .with_sleeper(clock.clone());
+
+        assert_eq!(Err(4), down.value(0));
+        assert_eq!(Duration::from_secs(1 + 2 + 3), clock.now() - start);
+        assert_eq!(4, down.failures(&0));
+        assert_eq!(Err(8), down.value(0));
+        assert_eq!(8, down.failures(&0));
+        assert_eq!(0, down.failures(&1));
+
+        down.invalidate(&0);
+        assert_eq!(0, down.failures(&0));
+        down.value(1).unwrap_err();
+        down.clear_failures();
+        assert_eq!(0, down.failures(&1));
+    }
+
+    /* Counts go once the key succeeds, and are bounded like the values */
+    #[test]
+    fn bounded_failures() {
+        let clock = ManualClock::new();
+        let mut odd = TryMemoizer::with_capacity_lru(2, |n: u32| match n & 1 {
+            1 => Ok(n),
+            _ => Err(n),
+        })
+        .with_retry(Backoff::new(2, Duration::from_secs(1)))
+        .with_sleeper(clock.clone())
+        .with_clock(clock.clone());
+
+        for n in (0..100).step_by(2) {
+            odd.value(n).unwrap_err();
+        }
+        assert_eq!(2, odd.failures.len());
+        assert_eq!(2, odd.failures(&98));
+        assert_eq!(0, odd.failures(&94));
+
+        let calls = Cell::new(0);
+        let mut flaky = TryMemoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            if calls.get() == 1 {
+                Err(n)
+            } else {
+                Ok(n)
+            }
+        });
+        flaky.value(1).unwrap_err();
+        assert_eq!(1, flaky.failures(&1));
+        assert_eq!(Ok(1), flaky.value(1));
+        assert_eq!(0, flaky.failures(&1));
+    }
+
+    /* Delays saturate instead of overflowing */
+    #[test]
+    fn backoff_overflow() {
+        let backoff = Backoff::new(100, Duration::from_secs(1));
+        assert_eq!(Duration::from_secs(8), backoff.delay(3));
+        assert_eq!(Duration::MAX, backoff.delay(90));
+    }
+
+    /* Cached errors expire, and a later success replaces them */
+    #[test]
+    fn negative_caching() {
+        let calls = Cell::new(0);
+        let clock = ManualClock::new();
+        let mut flaky = TryMemoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err("down")
+            } else {
+                Ok(n)
+            }
+        })
+        .with_error_ttl(Duration::from_secs(5))
+        .with_clock(clock.clone());
+
+        assert_eq!(Err("down"), flaky.value(7));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(Err("down"), flaky.value(7));
+        assert_eq!(1, calls.get());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(Err("down"), flaky.value(7));
+        assert_eq!(2, calls.get());
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(Ok(7), flaky.value(7));
+        assert!(flaky.errors.is_empty());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(Ok(7), flaky.value(7));
+        assert_eq!(3, calls.get());
+    }
+}
@@ -0,0 +1,221 @@
+//! Estimating how much memory memoized keys and values take up.
+
+// Imports
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Estimates the memory a value owns on the heap, on top of its own `size_of`. Used by memoizers with a memory budget to weigh their entries.
+///
+/// Shared pointers like [`Rc`] and [`Arc`] count everything they point to, as if the memoizer were the only owner. References count nothing, the memoizer doesn't own what they point to.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::HeapSize;
+/// struct Page {
+///     title: String,
+///     links: Vec<String>,
+/// }
+///
+/// impl HeapSize for Page {
+///     fn heap_size(&self) -> usize {
+///         self.title.heap_size() + self.links.heap_size()
+///     }
+/// }
+///
+/// let page = Page {
+///     title: String::from("gay"),
+///     links: Vec::new(),
+/// };
+/// assert_eq!(3, page.heap_size());
+/// ```
+///
+pub trait HeapSize {
+    /// Returns the number of bytes owned on the heap.
+    fn heap_size(&self) -> usize;
+}
+
+/* Types which own nothing on the heap */
+macro_rules! inline {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+inline!(
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    bool,
+    char,
+    (),
+    str,
+    Duration
+);
+
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for [T] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self[..].heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self[..].heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<(K, V)>()
+            + self
+                .iter()
+                .map(|(key, value)| key.heap_size() + value.heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        size_of_val(&**self) + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Rc<T> {
+    fn heap_size(&self) -> usize {
+        size_of_val(&**self) + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Arc<T> {
+    fn heap_size(&self) -> usize {
+        size_of_val(&**self) + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<T: HeapSize, E: HeapSize> HeapSize for Result<T, E> {
+    fn heap_size(&self) -> usize {
+        match self {
+            Ok(value) => value.heap_size(),
+            Err(error) => error.heap_size(),
+        }
+    }
+}
+
+macro_rules! tuple {
+    ($($name:ident)+) => {
+        impl<$($name: HeapSize),+> HeapSize for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn heap_size(&self) -> usize {
+                let ($($name,)+) = self;
+                0 $(+ $name.heap_size())+
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+tuple!(A B C D E);
+tuple!(A B C D E F);
+tuple!(A B C D E F G);
+tuple!(A B C D E F G H);
+tuple!(A B C D E F G H I);
+tuple!(A B C D E F G H I J);
+tuple!(A B C D E F G H I J K);
+tuple!(A B C D E F G H I J K L);
+
+/* Estimated bytes taken up by a memoized key and value */
+pub(crate) fn weigh<U: HeapSize, V: HeapSize>(key: &U, value: &V) -> usize {
+    size_of::<U>() + size_of::<V>() + key.heap_size() + value.heap_size()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Containers count their spare capacity and what their items own */
+    #[test]
+    fn containers() {
+        let words = vec![String::from("gay"), String::from("girls")];
+        assert_eq!(2 * size_of::<String>() + 8, words.heap_size());
+
+        let mut numbers: Vec<u32> = Vec::with_capacity(10);
+        numbers.push(1);
+        assert_eq!(40, numbers.heap_size());
+
+        let boxed: Box<str> = "gay".into();
+        assert_eq!(3, boxed.heap_size());
+        assert_eq!(size_of::<u64>(), Some(Box::new(1u64)).heap_size());
+        assert_eq!(0, None::<Box<u64>>.heap_size());
+    }
+
+    /* Tuples and arrays add up their parts, references own nothing */
+    #[test]
+    fn composites() {
+        let pair = (String::from("gay"), [String::from("girls"), String::new()]);
+        assert_eq!(8, pair.heap_size());
+        assert_eq!(0, (&String::from("gay"), 1u8).heap_size());
+        assert_eq!(
+            size_of::<u32>() + size_of::<(u32, String)>() + 3,
+            weigh(&1u32, &(2u32, String::from("gay")))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Stack safe memoization of recursive functions.
+
+// Imports
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::CycleError;
+
+/// Memoizes a recursive function without recursing on the thread's stack. Instead of calling itself, the function asks the [`Dependencies`] handle for the values it needs and returns `None` if any of them have not been computed yet. The memoizer then evaluates the missing dependencies from an explicit stack and calls the function again once they are available.
+///
+/// Functions are called more than once for keys with missing dependencies, so they should be cheap to restart up until the point where all of their dependencies are available.
+#[derive(Debug)]
+pub struct IterativeMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(&mut Dependencies<'_, U, V>, U) -> Option<V>,
+{
+    function: F,
+    map: HashMap<U, V>,
+}
+
+impl<U, V, F> IterativeMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(&mut Dependencies<'_, U, V>, U) -> Option<V>,
+{
+    /// Creates a new IterativeMemoizer given a function which yields its dependencies through a [`Dependencies`] handle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::IterativeMemoizer;
+    /// let mut fib = IterativeMemoizer::new(|deps, n: u64| {
+    ///     if n < 2 {
+    ///         Some(n)
+    ///     } else {
+    ///         Some(deps.get(n - 1)? + deps.get(n - 2)?)
+    ///     }
+    /// });
+    /// assert_eq!(12_586_269_025, fib.value(50));
+    /// ```
+    ///
+    pub fn new(function: F) -> IterativeMemoizer<U, V, F> {
+        IterativeMemoizer {
+            function,
+            map: HashMap::new(),
+        }
+    }
+
+    /// Returns the value for the memoized function. Dependencies which have not been computed before are evaluated iteratively, so arbitrarily long chains of dependencies will not overflow the stack.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function returns `None` without having requested a missing dependency, or if it depends on its own value. The message only says how long the cycle is, since keys needn't be `Debug`. Use [`IterativeMemoizer::try_value`] to handle cycles instead, its [`CycleError`] holds the chain of keys and prints them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::IterativeMemoizer;
+    /// // Far too deep to recurse on an ordinary thread stack
+    /// let mut triangle = IterativeMemoizer::new(|deps, n: u64| match n {
+    ///     0 => Some(0),
+    ///     n => Some(n + deps.get(n - 1)?),
+    /// });
+    /// assert_eq!(500_000_500_000, triangle.value(1_000_000));
+    /// ```
+    ///
+    pub fn value(&mut self, arg: U) -> V {
+        self.try_value(arg).unwrap_or_else(|error| error.panic())
+    }
+
+    /// Returns the value for the memoized function, or a [`CycleError`] describing the chain of dependencies if the function depends on its own value. Values computed before the cycle was found remain cached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function returns `None` without having requested a missing dependency.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::IterativeMemoizer;
+    /// // Distance along a chain of pointers to a node that points to itself
+    /// let next = [1, 2, 0, 3, 3];
+    /// let mut depth = IterativeMemoizer::new(|deps, n: usize| match next[n] {
+    ///     m if m == n => Some(0),
+    ///     m => Some(deps.get(m)? + 1),
+    /// });
+    ///
+    /// assert_eq!(Ok(1), depth.try_value(4));
+    /// let error = depth.try_value(0).unwrap_err();
+    /// assert_eq!(&[0, 1, 2, 0], error.chain());
+    /// ```
+    ///
+    pub fn try_value(&mut self, arg: U) -> Result<V, CycleError<U>> {
+        if let Some(value) = self.map.get(&arg) {
+            return Ok(value.clone());
+        }
+
+        // Keys which have called the function and are waiting on their
+        // dependencies, these are always an unbroken chain up the stack.
+        let mut on_stack: HashMap<U, usize> = HashMap::new();
+        let mut stack = vec![(arg.clone(), false)];
+        while let Some((key, waiting)) = stack.last_mut() {
+            if self.map.contains_key(key) {
+                stack.pop();
+                continue;
+            }
+
+            let key = key.clone();
+            if !*waiting {
+                *waiting = true;
+                on_stack.insert(key.clone(), stack.len() - 1);
+            }
+
+            let mut deps = Dependencies {
+                map: &self.map,
+                missing: Vec::new(),
+            };
+            match (self.function)(&mut deps, key.clone()) {
+                Some(value) => {
+                    on_stack.remove(&key);
+                    self.map.insert(key, value);
+                    stack.pop();
+                }
+                None => {
+                    assert!(
+                        !deps.missing.is_empty(),
+                        "memoized function returned None without requesting a missing dependency"
+                    );
+                    for dep in &deps.missing {
+                        if let Some(&i) = on_stack.get(dep) {
+                            let mut chain: Vec<U> = stack[i..]
+                                .iter()
+                                .filter(|(_, waiting)| *waiting)
+                                .map(|(key, _)| key.clone())
+                                .collect();
+                            chain.push(dep.clone());
+                            return Err(CycleError::new(chain));
+                        }
+                    }
+                    // Evaluate dependencies in the order they were requested
+                    stack.extend(deps.missing.into_iter().rev().map(|dep| (dep, false)));
+                }
+            }
+        }
+
+        Ok(self.map[&arg].clone())
+    }
+}
+
+/// Handle passed to the function of an [`IterativeMemoizer`], used to look up the values the function depends on.
+pub struct Dependencies<'a, U, V> {
+    map: &'a HashMap<U, V>,
+    missing: Vec<U>,
+}
+
+impl<'a, U, V> Dependencies<'a, U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Returns the value of the memoized function for `arg` if it has already been computed. Otherwise `arg` is recorded as a dependency to be evaluated before the function is called again, and `None` is returned so that the function can bail out early with `?`.
+    pub fn get(&mut self, arg: U) -> Option<V> {
+        match self.map.get(&arg) {
+            Some(value) => Some(value.clone()),
+            None => {
+                self.missing.push(arg);
+                None
+            }
+        }
+    }
+}
+
+impl<'a, U, V> fmt::Debug for Dependencies<'a, U, V>
+where
+    U: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dependencies")
+            .field("map", &self.map)
+            .field("missing", &self.missing)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /* Each sub-problem is only completed once */
+    #[test]
+    fn fibonacci() {
+        let completed = Cell::new(0);
+        let mut fib = IterativeMemoizer::new(|deps, n: u64| {
+            let value = if n < 2 {
+                n
+            } else {
+                deps.get(n - 1)? + deps.get(n - 2)?
+            };
+            completed.set(completed.get() + 1);
+            Some(value)
+        });
+
+        assert_eq!(55, fib.value(10));
+        assert_eq!(11, completed.get());
+
+        assert_eq!(34, fib.value(9));
+        assert_eq!(89, fib.value(11));
+        assert_eq!(12, completed.get());
+    }
+
+    /* A chain this long would overflow the stack if evaluated recursively */
+    #[test]
+    fn deep_recursion() {
+        const MODULUS: u64 = 1_000_000_007;
+        let mut fib = IterativeMemoizer::new(|deps, n: u64| {
+            if n < 2 {
+                Some(n)
+            } else {
+                Some((deps.get(n - 1)? + deps.get(n - 2)?) % MODULUS)
+            }
+        });
+
+        assert_eq!(918_091_266, fib.value(1_000_000));
+    }
+
+    /* Path counting on a grid, every cell depends on two others */
+    #[test]
+    fn grid_paths() {
+        let mut paths = IterativeMemoizer::new(|deps, (n, m): (u64, u64)| {
+            if n == 0 || m == 0 {
+                Some(1)
+            } else {
+                Some(deps.get((n - 1, m))? + deps.get((n, m - 1))?)
+            }
+        });
+
+        assert_eq!(6, paths.value((2, 2)));
+        assert_eq!(184_756, paths.value((10, 10)));
+    }
+
+    /* A cycle is reported with the chain of keys waiting on each other */
+    #[test]
+    fn cycle_error() {
+        let mut cyclic = IterativeMemoizer::new(|deps, n: u32| match n {
+            0 => Some(0),
+            n => Some(deps.get(n % 3 + 1)? + 1),
+        });
+
+        let error = cyclic.try_value(1).unwrap_err();
+        assert_eq!(&[1, 2, 3, 1], error.chain());
+
+        // Keys leading into a cycle are not part of it
+        let mut tail = IterativeMemoizer::new(|deps: &mut Dependencies<u32, u32>, n| match n {
+            0..=3 => Some(deps.get(n % 3 + 1)? + 1),
+            n => Some(deps.get(n - 1)? + 1),
+        });
+        assert_eq!(
+            vec![3, 1, 2, 3],
+            tail.try_value(5).unwrap_err().into_chain()
+        );
+    }
+
+    /* Depending directly on yourself is a cycle of one */
+    #[test]
+    fn self_dependency() {
+        let mut cyclic = IterativeMemoizer::new(|deps: &mut Dependencies<u32, u32>, n| deps.get(n));
+        assert_eq!(vec![7, 7], cyclic.try_value(7).unwrap_err().into_chain());
+    }
+
+    /* Diamond shaped dependencies are not mistaken for cycles */
+    #[test]
+    fn diamond() {
+        let mut diamond = IterativeMemoizer::new(|deps, n: u32| match n {
+            0 => Some(1),
+            1 | 2 => Some(deps.get(0)? + 1),
+            _ => Some(deps.get(1)? + deps.get(2)?),
+        });
+        assert_eq!(Ok(4), diamond.try_value(3));
+    }
+
+    /* Plain value() panics rather than looping forever */
+    #[test]
+    #[should_panic(expected = "depends on itself through a cycle of 2 keys")]
+    fn cycle_panics() {
+        let mut cyclic =
+            IterativeMemoizer::new(|deps: &mut Dependencies<u32, u32>, n| deps.get(1 - n));
+        cyclic.value(0);
+    }
+
+    /* Returning None without asking for anything is a bug in the function */
+    #[test]
+    #[should_panic(expected = "without requesting a missing dependency")]
+    fn none_without_dependency() {
+        let mut broken = IterativeMemoizer::new(|_: &mut Dependencies<u32, u32>, _| None);
+        broken.value(0);
+    }
+}
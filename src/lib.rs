@@ -8,69 +8,719 @@
         unused_import_braces, unused_qualifications)]
 
 // Imports
-use std::collections::HashMap;
+use std::borrow::Borrow;
 use std::hash::Hash;
+use std::time::Duration;
 
-/// The eponymous struct. Can only memoize function that takes a single argument and returns a single value, if you need more than this, you can use vectors, arrays or structs of your own to pass in more than one value.
+mod arity;
+mod asynchronous;
+mod clock;
+mod entry;
+mod error;
+mod fallible;
+mod heap_size;
+mod iterative;
+mod memoize;
+pub mod policy;
+mod random;
+mod recursive;
+mod refresh;
+mod store;
+mod sync;
+pub use arity::{
+    Memoizer0, Memoizer10, Memoizer11, Memoizer12, Memoizer2, Memoizer3, Memoizer4, Memoizer5,
+    Memoizer6, Memoizer7, Memoizer8, Memoizer9,
+};
+pub use asynchronous::AsyncMemoizer;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use error::CycleError;
+pub use fallible::{Backoff, Sleep, TryMemoizer};
+pub use heap_size::HeapSize;
+pub use iterative::{Dependencies, IterativeMemoizer};
+pub use memoize::{CacheStats, MethodMemoizer};
+#[cfg(feature = "macros")]
+pub use memoizer_macros::memoize;
+pub use recursive::{Recursion, RecursiveMemoizer};
+pub use refresh::{Spawn, Task, Worker};
+pub use sync::SyncMemoizer;
+
+/* Used by the code #[memoize] generates */
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::memoize::{Cache, Global};
+}
+
+use refresh::Refresher;
+use store::Store;
+
+use policy::{AdaptiveReplacement, Gdsf, Lru, Policy, S3Fifo, Sieve, TinyLfu};
+
+/// The eponymous struct. Can only memoize function that takes a single argument and returns a single value, if you need more than this, use [`Memoizer2`] to [`Memoizer12`], or vectors, arrays or structs of your own to pass in more than one value.
+///
+/// By default every value is kept forever. A memoizer created with a capacity evicts values chosen by its [`Policy`] to stay within that capacity, least recently used ones unless told otherwise. Values can also be given a time to live, after which they are computed again.
+///
+/// Values which are expensive to clone can be stored behind an [`Arc`](std::sync::Arc) or [`Rc`](std::rc::Rc) by wrapping them in the function, with any constructor. [`Memoizer::value`] then hands out another handle to the same value instead of cloning it, and values shared this way can't be changed.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::Memoizer;
+/// use std::sync::Arc;
+///
+/// fn squares(n: usize) -> Vec<usize> {
+///     (0..n).map(|i| i * i).collect()
+/// }
+///
+/// let mut shared = Memoizer::with_capacity_lru(100, |n| Arc::new(squares(n)));
+/// let first = shared.value(1000);
+/// assert!(Arc::ptr_eq(&first, &shared.value(1000)));
+/// ```
+///
 #[derive(Debug)]
-pub struct Memoizer<U, V, F>
+pub struct Memoizer<U, V, F, P = Lru<U>>
 where
     U: Eq + Hash + Clone,
-    V: Clone,
     F: Fn(U) -> V,
+    P: Policy<U>,
 {
     function: F,
-    map: HashMap<U, V>,
+    store: Store<U, V, P>,
 }
 
 impl<U, V, F> Memoizer<U, V, F>
 where
     U: Eq + Hash + Clone,
-    V: Clone,
     F: Fn(U) -> V,
 {
-	/// Creates a new Memoize given a function.
-	///
-	/// # Examples
-	///
-	/// ```
-	///# use memoizer::Memoizer;
-	/// let mut add_two = Memoizer::new(|n| {
-	///		n + 2
-	///	});
-	/// assert_eq!(4, add_two.value(2));
-	/// ```
-	///
+    /// Creates a new Memoize given a function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| {
+    ///     n + 2
+    /// });
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
     pub fn new(function: F) -> Memoizer<U, V, F> {
         Memoizer {
             function,
-            map: HashMap::new(),
+            store: Store::new(None, Lru::new()),
         }
     }
 
-	/// Returns the value for the memoized function. If the function has already been called before, it will use the previous value. This means Memoizer should only be used for injective functions.
-	///
-	/// # Examples
-	///
-	/// ```
-	///
-	///# use memoizer::Memoizer;
-	/// #[derive(Debug, Clone, Hash)]
-	///	 struct Dummy {
-	///	     pub id: usize,
-	///	     pub word: String,
-	///	 }
-	///
-	///	 /* PartialEq & Eq required for HashMap */
-	///	 impl PartialEq for Dummy {
-	///	     fn eq(&self, other: &Dummy) -> bool {
-	///	         self.id == other.id && self.word == other.word
-	///	     }
-	///	 }
-	///
-	///	impl Eq for Dummy {}
-	///
-	/// let d = Dummy {
+    /// Creates a new Memoize given a function, which holds on to at most `capacity` values by evicting the least recently used one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_lru(2, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// assert_eq!(5, add_two.value(3));
+    /// assert_eq!(4, add_two.value(2));
+    ///
+    /// // Evicts 3, it was used longest ago
+    /// assert_eq!(6, add_two.value(4));
+    /// ```
+    ///
+    pub fn with_capacity_lru(capacity: usize, function: F) -> Memoizer<U, V, F> {
+        Memoizer::with_policy(capacity, Lru::new(), function)
+    }
+}
+
+impl<U, V, F> Memoizer<U, V, F, TinyLfu<U>>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new Memoize given a function, which holds on to at most `capacity` values using the [`TinyLfu`] policy to decide which values are worth keeping.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_tiny_lfu(100, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_capacity_tiny_lfu(capacity: usize, function: F) -> Memoizer<U, V, F, TinyLfu<U>> {
+        Memoizer::with_policy(capacity, TinyLfu::new(capacity), function)
+    }
+}
+
+impl<U, V, F> Memoizer<U, V, F, AdaptiveReplacement<U>>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new Memoizer given a function, which holds on to at most `capacity` values using the [`AdaptiveReplacement`] (ARC) policy to balance keeping recently and frequently used values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_arc(100, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_capacity_arc(
+        capacity: usize,
+        function: F,
+    ) -> Memoizer<U, V, F, AdaptiveReplacement<U>> {
+        Memoizer::with_policy(capacity, AdaptiveReplacement::new(capacity), function)
+    }
+}
+
+impl<U, V, F> Memoizer<U, V, F, Sieve<U>>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new Memoizer given a function, which holds on to at most `capacity` values using the [`Sieve`] policy. Hits only mark the value as visited, which keeps them nearly as cheap as in an unbounded Memoizer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_sieve(100, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_capacity_sieve(capacity: usize, function: F) -> Memoizer<U, V, F, Sieve<U>> {
+        Memoizer::with_policy(capacity, Sieve::new(), function)
+    }
+}
+
+impl<U, V, F> Memoizer<U, V, F, S3Fifo<U>>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new Memoizer given a function, which holds on to at most `capacity` values using the [`S3Fifo`] policy. Hits only bump a counter, and values used just once are evicted quickly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_s3_fifo(100, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_capacity_s3_fifo(capacity: usize, function: F) -> Memoizer<U, V, F, S3Fifo<U>> {
+        Memoizer::with_policy(capacity, S3Fifo::new(capacity), function)
+    }
+}
+
+impl<U, V, F> Memoizer<U, V, F, Gdsf<U>>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new Memoizer given a function, which holds on to at most `capacity` values using the [`Gdsf`] policy. Every call to the function is timed, and the values which were quickest to compute are evicted first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::with_capacity_gdsf(100, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_capacity_gdsf(capacity: usize, function: F) -> Memoizer<U, V, F, Gdsf<U>> {
+        Memoizer::with_policy(capacity, Gdsf::new(), function)
+    }
+}
+
+impl<U, V, F, P> Memoizer<U, V, F, P>
+where
+    U: Eq + Hash + Clone,
+    F: Fn(U) -> V,
+    P: Policy<U>,
+{
+    /// Creates a new Memoize given a function, which holds on to at most `capacity` values by evicting the ones chosen by `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// use memoizer::policy::Lru;
+    ///
+    /// let mut add_two = Memoizer::with_policy(100, Lru::new(), |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_policy(capacity: usize, policy: P, function: F) -> Memoizer<U, V, F, P> {
+        Memoizer {
+            function,
+            store: Store::new(Some(capacity), policy),
+        }
+    }
+
+    /// Sets how long values are kept after being computed. Once a value has expired, asking for it again calls the function again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// use std::time::Duration;
+    ///
+    /// let mut rate = Memoizer::new(|currency: &str| currency.len()).with_ttl(Duration::from_secs(60));
+    /// assert_eq!(3, rate.value("EUR"));
+    /// ```
+    ///
+    pub fn with_ttl(mut self, ttl: Duration) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_ttl(ttl);
+        self
+    }
+
+    /// Sets a function deciding how long each value is kept, given its key and the value itself. Values it returns None for fall back to the time set by [`Memoizer::with_ttl`], or are kept forever if there is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, Memoizer};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let mut lookup = Memoizer::new(|key: &str| key.len())
+    ///     .with_expiry(|key, _| match *key {
+    ///         "rate" => Some(Duration::from_secs(1)),
+    ///         _ => None,
+    ///     })
+    ///     .with_clock(clock.clone());
+    ///
+    /// lookup.value("rate");
+    /// lookup.value("config");
+    /// clock.advance(Duration::from_secs(1));
+    /// assert_eq!(1, lookup.purge_expired());
+    /// ```
+    ///
+    pub fn with_expiry(mut self, expiry: fn(&U, &V) -> Option<Duration>) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_expiry(expiry);
+        self
+    }
+
+    /// Sets the clock used to decide when values expire, instead of the system clock. Useful for testing with a [`ManualClock`].
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_clock(clock);
+        self
+    }
+
+    /// Keeps serving values for up to `stale` after they expire, while they are recomputed in the background. Only has an effect once the memoizer has a refresher, see [`Memoizer::with_refresher`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, Memoizer, Worker};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let mut total = Memoizer::new(|n: u64| (0..=n).sum::<u64>())
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_stale_while_revalidate(Duration::from_secs(10))
+    ///     .with_refresher(Worker::new())
+    ///     .with_clock(clock.clone());
+    ///
+    /// assert_eq!(55, total.value(10));
+    /// clock.advance(Duration::from_secs(65));
+    /// // Expired, but returned straight away while a new value is computed
+    /// assert_eq!(55, total.value(10));
+    /// ```
+    ///
+    pub fn with_stale_while_revalidate(mut self, stale: Duration) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_stale_while_revalidate(stale);
+        self
+    }
+
+    /// Recomputes values in the background when they are asked for less than `ahead` before they expire, so that they are rarely seen expired at all. Only has an effect once the memoizer has a refresher, see [`Memoizer::with_refresher`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Memoizer, Worker};
+    /// use std::time::Duration;
+    ///
+    /// let mut add_two = Memoizer::new(|n: u64| n + 2)
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_refresh_ahead(Duration::from_secs(5))
+    ///     .with_refresher(Worker::new());
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_refresh_ahead(mut self, ahead: Duration) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_refresh_ahead(ahead);
+        self
+    }
+
+    /// Recomputes values probabilistically before they expire, so that values which were computed together don't all expire at once. Every call to the function is timed, and a value is treated as expired with a chance that grows as its expiry gets closer and the longer it took to compute, scaled by `beta`. A `beta` of 1 is a good start, larger values recompute earlier.
+    ///
+    /// With a refresher and [`Memoizer::with_stale_while_revalidate`] the early recomputation happens in the background.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `beta` is negative or not finite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// use std::time::Duration;
+    ///
+    /// let mut add_two = Memoizer::new(|n| n + 2)
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_early_expiration(1.0);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_early_expiration(mut self, beta: f64) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_early_expiration(beta);
+        self
+    }
+
+    /// Shortens the time to live of each value by a random amount, up to `fraction` of it, so that values computed together expire at different times. The time set by [`Memoizer::with_ttl`] or [`Memoizer::with_expiry`] stays the longest a value is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not between 0 and 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// use std::time::Duration;
+    ///
+    /// // Each value lives for somewhere between 50 and 60 seconds
+    /// let mut add_two = Memoizer::new(|n| n + 2)
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_ttl_jitter(1.0 / 6.0);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_ttl_jitter(mut self, fraction: f64) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_ttl_jitter(fraction);
+        self
+    }
+
+    /// Returns the maximum number of values the memoizer holds on to, or None if it is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.store.capacity()
+    }
+
+    /// Returns the maximum number of bytes the memoizer's keys and values may take up, or None if it has no memory budget.
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.store.memory_budget()
+    }
+
+    /// Returns the estimated number of bytes taken up by the memoizer's keys and values, or None if it has no memory budget and isn't keeping track.
+    pub fn memory_used(&self) -> Option<usize> {
+        self.store.memory_used()
+    }
+
+    /// Returns a reference to the value for the memoized function, computing it if it isn't cached yet. Unlike [`Memoizer::value`] nothing is cloned, so values don't need to be `Clone`, and the reference lasts until the memoizer is next used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// // Not Clone
+    /// struct Matrix(Vec<Vec<f64>>);
+    ///
+    /// let mut identity = Memoizer::new(|n: usize| {
+    ///     Matrix((0..n).map(|i| (0..n).map(|j| (i == j) as u8 as f64).collect()).collect())
+    /// });
+    /// assert_eq!(1.0, identity.value_ref(3).0[2][2]);
+    /// ```
+    ///
+    pub fn value_ref(&mut self, arg: U) -> &V {
+        self.store.value_ref(arg, &self.function)
+    }
+
+    /// Returns the [`Entry`] for the key, which can be looked at and filled in by hand. A cached value which has expired is dropped, leaving the entry vacant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Entry, Memoizer};
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// match add_two.entry(2) {
+    ///     Entry::Occupied(entry) => println!("cached {}", entry.get()),
+    ///     Entry::Vacant(entry) => println!("computed {}", entry.compute()),
+    /// }
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn entry(&mut self, key: U) -> Entry<'_, U, V, &F, P> {
+        Entry::new(&mut self.store, key, &self.function)
+    }
+
+    /// Removes every value which has expired, returning how many there were. Expired values are otherwise only removed when they are asked for again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{ManualClock, Memoizer};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let mut add_two = Memoizer::new(|n| n + 2)
+    ///     .with_ttl(Duration::from_secs(10))
+    ///     .with_clock(clock.clone());
+    ///
+    /// add_two.value(1);
+    /// clock.advance(Duration::from_secs(5));
+    /// add_two.value(2);
+    /// clock.advance(Duration::from_secs(5));
+    /// assert_eq!(1, add_two.purge_expired());
+    /// ```
+    ///
+    pub fn purge_expired(&mut self) -> usize {
+        self.store.purge_expired()
+    }
+
+    /// Returns whether a value for the key is cached and hasn't expired, without computing it or counting as a use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert!(!add_two.contains(&2));
+    /// add_two.value(2);
+    /// assert!(add_two.contains(&2));
+    /// ```
+    ///
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.contains(key)
+    }
+
+    /// Returns the cached value for the key if there is one which hasn't expired, without computing it or counting as a use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(None, add_two.peek(&2));
+    /// add_two.value(2);
+    /// assert_eq!(Some(&4), add_two.peek(&2));
+    /// ```
+    ///
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.peek(key)
+    }
+
+    /// Caches a value for the key without calling the function, returning the value it replaced if there was one. The value expires and counts towards the capacity like a computed one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert_eq!(None, add_two.insert(2, 5));
+    /// assert_eq!(5, add_two.value(2));
+    /// assert_eq!(Some(5), add_two.insert(2, 4));
+    /// ```
+    ///
+    pub fn insert(&mut self, key: U, value: V) -> Option<V> {
+        self.store.insert(key, value)
+    }
+
+    /// Caches a value for the key without calling the function, unless a value which hasn't expired is cached already. Returns whether the value was cached. Useful for seeding the memoizer with answers known ahead of time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut fib = Memoizer::new(|n: u64| (0..n).fold((0u64, 1), |(a, b), _| (b, a + b)).0);
+    /// assert!(fib.prime(90, 2_880_067_194_370_816_120));
+    /// assert!(!fib.prime(90, 0));
+    /// assert_eq!(2_880_067_194_370_816_120, fib.value(90));
+    /// ```
+    ///
+    pub fn prime(&mut self, key: U, value: V) -> bool {
+        self.store.prime(key, value)
+    }
+
+    /// Removes the value for the key, returning it if it was cached, so that the function is called again the next time it is asked for.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// assert_eq!(Some(4), add_two.invalidate(&2));
+    /// assert_eq!(None, add_two.invalidate(&2));
+    /// ```
+    ///
+    pub fn invalidate<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.invalidate(key)
+    }
+
+    /// Removes every cached value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// add_two.clear();
+    /// assert!(add_two.is_empty());
+    /// ```
+    ///
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    /// Returns the number of cached values, including expired ones which haven't been removed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// add_two.value(2);
+    /// add_two.value(3);
+    /// assert_eq!(2, add_two.len());
+    /// ```
+    ///
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns whether there are no cached values, including expired ones which haven't been removed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// assert!(add_two.is_empty());
+    /// add_two.value(2);
+    /// assert!(!add_two.is_empty());
+    /// ```
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Iterates over the cached keys and values which haven't expired, in no particular order. Doesn't count as a use of any of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// add_two.value(3);
+    /// assert_eq!(9, add_two.iter().map(|(_, value)| value).sum());
+    /// ```
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&U, &V)> + '_ {
+        self.store.iter()
+    }
+
+    /// Iterates over the cached keys whose values haven't expired, in no particular order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// assert_eq!(vec![&2], add_two.keys().collect::<Vec<_>>());
+    /// ```
+    ///
+    pub fn keys(&self) -> impl Iterator<Item = &U> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Keeps only the cached values for which `keep` returns true, removing the rest.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// for n in 0..10 {
+    ///     add_two.value(n);
+    /// }
+    /// add_two.retain(|n, _| n % 2 == 0);
+    /// assert_eq!(5, add_two.len());
+    /// ```
+    ///
+    pub fn retain<R>(&mut self, keep: R)
+    where
+        R: FnMut(&U, &V) -> bool,
+    {
+        self.store.retain(keep);
+    }
+
+    /// Removes every cached value, returning the keys and values in no particular order. Expired values are included. Values not taken from the iterator are dropped along with it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut add_two = Memoizer::new(|n| n + 2);
+    /// add_two.value(2);
+    /// assert_eq!(vec![(2, 4)], add_two.drain().collect::<Vec<_>>());
+    /// assert!(add_two.is_empty());
+    /// ```
+    ///
+    pub fn drain(&mut self) -> impl Iterator<Item = (U, V)> + '_ {
+        self.store.drain()
+    }
+}
+
+impl<U, V, F, P> Memoizer<U, V, F, P>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> V,
+    P: Policy<U>,
+{
+    /// Returns the value for the memoized function. If the function has already been called before, it will use the previous value. This means Memoizer should only be used for injective functions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///
+    ///# use memoizer::Memoizer;
+    /// #[derive(Debug, Clone, Hash)]
+    ///  struct Dummy {
+    ///      pub id: usize,
+    ///      pub word: String,
+    ///  }
+    ///
+    ///  /* PartialEq & Eq required for HashMap */
+    ///  impl PartialEq for Dummy {
+    ///      fn eq(&self, other: &Dummy) -> bool {
+    ///          self.id == other.id && self.word == other.word
+    ///      }
+    ///  }
+    ///
+    /// impl Eq for Dummy {}
+    ///
+    /// let d = Dummy {
     ///     id: 1,
     ///     word: String::from("girls"),
     /// };
@@ -78,18 +728,126 @@ where
     ///
     ///  assert_eq!(6, calc.value(&d));
     ///  assert_eq!(6, calc.value(&d));
-	/// ```
-	///
+    /// ```
+    ///
     pub fn value(&mut self, arg: U) -> V {
-        let f = &self.function;
-        let key = arg.clone();
-        self.map.entry(key).or_insert_with(|| { (f)(arg) }).clone()
+        self.value_ref(arg).clone()
+    }
+
+    /// Returns the value for the memoized function, looking it up by a borrowed form of the key. The owned key is only created, with `to_owned`, if the value isn't cached yet. Saves allocating a `String` or `Vec` key on every lookup.
+    ///
+    /// The memoized function takes the owned key, so on a miss it is called with a clone of the key which is then stored, allocating twice. [`Memoizer::value_by_ref_with`] calls a function with the borrowed key instead, allocating once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut length = Memoizer::new(|s: String| s.len());
+    /// assert_eq!(8, length.value_by_ref("gaygirls"));
+    /// assert_eq!(8, length.value_by_ref("gaygirls"));
+    /// ```
+    ///
+    pub fn value_by_ref<Q>(&mut self, key: &Q) -> V
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = U> + ?Sized,
+    {
+        if self.store.cached(key) {
+            return self.store.map[key].value.clone();
+        }
+        self.store.compute(key.to_owned(), &self.function).clone()
+    }
+
+    /// Returns the value looked up by a borrowed form of the key, calling `function` with the borrowed key if it isn't cached yet. The owned key is only created to be stored, so a miss allocates it once. Values refreshed in the background are still computed by the memoized function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// let mut length = Memoizer::new(|s: String| s.len());
+    /// assert_eq!(8, length.value_by_ref_with("gaygirls", str::len));
+    /// assert_eq!(8, length.value_by_ref("gaygirls"));
+    /// ```
+    ///
+    pub fn value_by_ref_with<Q, G>(&mut self, key: &Q, function: G) -> V
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = U> + ?Sized,
+        G: FnOnce(&Q) -> V,
+    {
+        if self.store.cached(key) {
+            return self.store.map[key].value.clone();
+        }
+        let (value, delta) = self.store.time(|| function(key));
+        self.store.cache(key.to_owned(), value, delta).clone()
+    }
+}
+
+impl<U, V, F, P> Memoizer<U, V, F, P>
+where
+    U: Eq + Hash + Clone + HeapSize,
+    V: HeapSize,
+    F: Fn(U) -> V,
+    P: Policy<U>,
+{
+    /// Limits the estimated number of bytes taken up by the memoizer's keys and values, as measured by [`HeapSize`]. Once it goes over budget the memoizer evicts values chosen by its policy, as it would when over capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::Memoizer;
+    /// // At most 1 MiB of words
+    /// let mut repeat = Memoizer::new(|n: usize| "gay".repeat(n)).with_memory_budget(1 << 20);
+    /// assert_eq!("gaygay", repeat.value(2));
+    /// assert!(repeat.memory_used().unwrap() <= 1 << 20);
+    /// ```
+    ///
+    pub fn with_memory_budget(mut self, bytes: usize) -> Memoizer<U, V, F, P> {
+        self.store = self.store.with_memory_budget(bytes);
+        self
+    }
+}
+
+impl<U, V, F, P> Memoizer<U, V, F, P>
+where
+    U: Eq + Hash + Clone + Send + 'static,
+    V: Send + 'static,
+    F: Fn(U) -> V + Clone + Send + Sync + 'static,
+    P: Policy<U>,
+{
+    /// Lets the memoizer recompute values in the background with a copy of its function, running them on `spawner`. A refreshed value replaces the old one the next time the memoizer is used, and each key is only refreshed once at a time. Refreshes are triggered by [`Memoizer::with_stale_while_revalidate`] and [`Memoizer::with_refresh_ahead`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::{Memoizer, Worker};
+    /// use std::time::Duration;
+    ///
+    /// let mut add_two = Memoizer::new(|n: u64| n + 2)
+    ///     .with_ttl(Duration::from_secs(60))
+    ///     .with_stale_while_revalidate(Duration::from_secs(60))
+    ///     .with_refresher(Worker::new());
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_refresher<S: Spawn + 'static>(mut self, spawner: S) -> Memoizer<U, V, F, P> {
+        let refresher = Refresher::new(self.function.clone(), spawner);
+        self.store = self.store.with_refresher(refresher);
+        self
     }
 }
 
 #[cfg(test)]
+/* Lints the original tests trip over */
+#[allow(
+    clippy::derived_hash_with_manual_eq,
+    clippy::let_and_return,
+    clippy::useless_vec
+)]
 mod tests {
     use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
 
     /* Constructor and closure testing */
     #[test]
@@ -233,4 +991,493 @@ mod tests {
             assert_eq!(calculated_v[i], assert_v[i]);
         }
     }
+
+    /* Only the least recently used values are evicted */
+    #[test]
+    fn lru() {
+        let calls = std::cell::Cell::new(0);
+        let mut add_two = Memoizer::with_capacity_lru(2, |n| {
+            calls.set(calls.get() + 1);
+            n + 2
+        });
+        assert_eq!(Some(2), add_two.capacity());
+
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(5, add_two.value(3));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(2, calls.get());
+
+        // 3 is evicted, 2 was used more recently
+        assert_eq!(6, add_two.value(4));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(3, calls.get());
+        assert_eq!(5, add_two.value(3));
+        assert_eq!(4, calls.get());
+        assert_eq!(2, add_two.store.map.len());
+    }
+
+    /* A memoizer with no room still returns values */
+    #[test]
+    fn zero_capacity() {
+        let calls = std::cell::Cell::new(0);
+        let mut add_two = Memoizer::with_capacity_lru(0, |n| {
+            calls.set(calls.get() + 1);
+            n + 2
+        });
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(2, calls.get());
+        assert!(add_two.store.map.is_empty());
+    }
+
+    /* Expired values are computed again */
+    #[test]
+    fn ttl() {
+        let calls = std::cell::Cell::new(0);
+        let clock = ManualClock::new();
+        let mut add_two = Memoizer::new(|n| {
+            calls.set(calls.get() + 1);
+            n + 2
+        })
+        .with_ttl(Duration::from_secs(10))
+        .with_clock(clock.clone());
+
+        assert_eq!(4, add_two.value(2));
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(1, calls.get());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(2, calls.get());
+    }
+
+    /* Each value can have its own time to live, or none at all */
+    #[test]
+    fn expiry() {
+        let clock = ManualClock::new();
+        let mut add_two = Memoizer::new(|n: u64| n + 2)
+            .with_ttl(Duration::from_secs(10))
+            .with_expiry(|&n, _| match n {
+                0 => Some(Duration::from_secs(1)),
+                _ => None,
+            })
+            .with_clock(clock.clone());
+
+        add_two.value(0);
+        add_two.value(1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(1, add_two.purge_expired());
+        assert!(add_two.store.map.contains_key(&1));
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(1, add_two.purge_expired());
+        assert!(add_two.store.map.is_empty());
+
+        // Without any time to live nothing expires
+        let mut forever = Memoizer::new(|n: u64| n + 2).with_clock(clock.clone());
+        forever.value(0);
+        clock.advance(Duration::from_secs(1_000_000));
+        assert_eq!(0, forever.purge_expired());
+    }
+
+    /* Purged values are forgotten by the policy too */
+    #[test]
+    fn purge_bounded() {
+        let clock = ManualClock::new();
+        let mut add_two = Memoizer::with_capacity_lru(2, |n: u64| n + 2)
+            .with_ttl(Duration::from_secs(10))
+            .with_clock(clock.clone());
+
+        add_two.value(0);
+        add_two.value(1);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(2, add_two.purge_expired());
+        add_two.value(2);
+        add_two.value(3);
+        assert_eq!(2, add_two.store.map.len());
+        assert_eq!(Some(2), add_two.store.policy.evict());
+    }
+
+    /* Runs background tasks only when the test says so */
+    fn queued() -> (impl Spawn, impl Fn()) {
+        let tasks = Arc::new(std::sync::Mutex::new(Vec::<Task>::new()));
+        let queue = Arc::clone(&tasks);
+        let spawner = move |task| queue.lock().unwrap().push(task);
+        let run = move || tasks.lock().unwrap().drain(..).for_each(|task| task());
+        (spawner, run)
+    }
+
+    /* Expired values are served while they are refreshed, until too stale */
+    #[test]
+    fn stale_while_revalidate() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static VERSION: AtomicU64 = AtomicU64::new(0);
+        let clock = ManualClock::new();
+        let (spawner, run) = queued();
+        let version = |n: u64| n * 10 + VERSION.fetch_add(1, Ordering::SeqCst);
+        let mut versioned = Memoizer::new(version)
+            .with_ttl(Duration::from_secs(10))
+            .with_stale_while_revalidate(Duration::from_secs(5))
+            .with_refresher(spawner)
+            .with_clock(clock.clone());
+
+        assert_eq!(10, versioned.value(1));
+        clock.advance(Duration::from_secs(12));
+        assert_eq!(10, versioned.value(1));
+        assert_eq!(10, versioned.value(1));
+        run();
+        assert_eq!(11, versioned.value(1));
+
+        // Past the grace period the caller waits for a new value
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(12, versioned.value(1));
+        run();
+        assert_eq!(12, versioned.value(1));
+    }
+
+    /* Values used shortly before expiring are refreshed early */
+    #[test]
+    fn refresh_ahead() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static VERSION: AtomicU64 = AtomicU64::new(0);
+        let clock = ManualClock::new();
+        let (spawner, run) = queued();
+        let version = |n: u64| n * 10 + VERSION.fetch_add(1, Ordering::SeqCst);
+        let mut versioned = Memoizer::new(version)
+            .with_ttl(Duration::from_secs(10))
+            .with_refresh_ahead(Duration::from_secs(2))
+            .with_refresher(spawner)
+            .with_clock(clock.clone());
+
+        assert_eq!(10, versioned.value(1));
+        clock.advance(Duration::from_secs(7));
+        versioned.value(1);
+        run();
+        assert_eq!(10, versioned.value(1));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(10, versioned.value(1));
+        run();
+        assert_eq!(11, versioned.value(1));
+
+        // The refreshed value lives for a full ttl from when it landed
+        clock.advance(Duration::from_secs(7));
+        assert_eq!(11, versioned.value(1));
+
+        // Looking further ahead than the clock can count doesn't overflow
+        let mut add_two = Memoizer::new(|n: u64| n + 2)
+            .with_ttl(Duration::from_secs(10))
+            .with_refresh_ahead(Duration::MAX)
+            .with_refresher(queued().0);
+        add_two.value(1);
+        assert_eq!(3, add_two.value(1));
+    }
+
+    /* A real worker thread eventually lands the refresh */
+    #[test]
+    fn worker_refresh() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static VERSION: AtomicU64 = AtomicU64::new(0);
+        let clock = ManualClock::new();
+        let version = |n: u64| n * 10 + VERSION.fetch_add(1, Ordering::SeqCst);
+        let mut versioned = Memoizer::new(version)
+            .with_ttl(Duration::from_secs(10))
+            .with_stale_while_revalidate(Duration::from_secs(10))
+            .with_refresher(Worker::new())
+            .with_clock(clock.clone());
+
+        assert_eq!(10, versioned.value(1));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(10, versioned.value(1));
+        for _ in 0..500 {
+            if versioned.value(1) == 11 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("refresh never landed");
+    }
+
+    /* Slow values are recomputed early, or not at all with a beta of 0 */
+    #[test]
+    fn early_expiration() {
+        fn calls(beta: f64) -> usize {
+            let calls = std::cell::Cell::new(0);
+            let clock = ManualClock::new();
+            let mut slow = Memoizer::new(|n: u64| {
+                calls.set(calls.get() + 1);
+                std::thread::sleep(Duration::from_millis(20));
+                n + 2
+            })
+            .with_ttl(Duration::from_secs(10))
+            .with_early_expiration(beta)
+            .with_clock(clock.clone());
+
+            slow.value(0);
+            clock.advance(Duration::from_secs(1));
+            slow.value(0);
+            calls.get()
+        }
+
+        assert_eq!(1, calls(0.0));
+        assert_eq!(2, calls(1e9));
+        assert_eq!(2, calls(1e300));
+    }
+
+    /* Jitter spreads expiry times out, but never past the ttl */
+    #[test]
+    fn ttl_jitter() {
+        let clock = ManualClock::new();
+        let mut add_two = Memoizer::new(|n: u64| n + 2)
+            .with_ttl(Duration::from_secs(100))
+            .with_ttl_jitter(0.5)
+            .with_clock(clock.clone());
+        for n in 0..100 {
+            add_two.value(n);
+        }
+
+        clock.advance(Duration::from_secs(49));
+        assert_eq!(0, add_two.purge_expired());
+        clock.advance(Duration::from_secs(26));
+        let purged = add_two.purge_expired();
+        assert!(purged > 0 && purged < 100);
+        clock.advance(Duration::from_secs(25));
+        assert_eq!(100 - purged, add_two.purge_expired());
+    }
+
+    #[test]
+    #[should_panic(expected = "between 0 and 1")]
+    fn jitter_out_of_range() {
+        let _ = Memoizer::new(|n: u64| n).with_ttl_jitter(1.5);
+    }
+
+    /* Values are evicted by the policy to stay within the memory budget */
+    #[test]
+    fn memory_budget() {
+        let entry = |n: usize| size_of::<usize>() + size_of::<Vec<u8>>() + n;
+        let mut bytes = Memoizer::new(|n: usize| vec![0u8; n]).with_memory_budget(entry(1000) * 2);
+        assert_eq!(Some(0), bytes.memory_used());
+
+        bytes.value(1000);
+        bytes.value(400);
+        assert_eq!(Some(entry(1000) + entry(400)), bytes.memory_used());
+        bytes.value(700);
+        assert!(!bytes.store.map.contains_key(&1000));
+        assert_eq!(Some(entry(400) + entry(700)), bytes.memory_used());
+
+        // Too big to keep at all
+        bytes.value(5000);
+        assert_eq!(Some(0), bytes.memory_used());
+        assert!(bytes.store.map.is_empty());
+
+        // Counted as many as the capacity, whichever runs out first
+        let mut both =
+            Memoizer::with_capacity_lru(2, |n: usize| vec![0u8; n]).with_memory_budget(1 << 20);
+        both.value(1);
+        both.value(2);
+        both.value(3);
+        assert_eq!(Some(entry(2) + entry(3)), both.memory_used());
+        assert_eq!(None, Memoizer::new(|n: u8| n).memory_used());
+    }
+
+    /* Looking up by a borrowed key only creates the owned one on a miss */
+    #[test]
+    fn value_by_ref() {
+        use std::cell::Cell;
+
+        thread_local!(static CLONES: Cell<usize> = const { Cell::new(0) });
+
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Key(u32);
+
+        impl Clone for Key {
+            fn clone(&self) -> Key {
+                CLONES.with(|clones| clones.set(clones.get() + 1));
+                Key(self.0)
+            }
+        }
+
+        let clones = || CLONES.with(Cell::get);
+        let mut add_two = Memoizer::new(|key: Key| key.0 + 2);
+        assert_eq!(4, add_two.value_by_ref(&Key(2)));
+        let after_miss = clones();
+        assert_eq!(2, after_miss);
+        for _ in 0..10 {
+            assert_eq!(4, add_two.value_by_ref(&Key(2)));
+        }
+        assert_eq!(after_miss, clones());
+
+        // Calling with the borrowed key only clones it to store it
+        assert_eq!(5, add_two.value_by_ref_with(&Key(3), |key| key.0 + 2));
+        assert_eq!(after_miss + 1, clones());
+        assert_eq!(5, add_two.value_by_ref_with(&Key(3), |_| unreachable!()));
+        assert_eq!(after_miss + 1, clones());
+
+        // Strings are looked up by &str
+        let mut length = Memoizer::with_capacity_lru(1, |s: String| s.len());
+        assert_eq!(3, length.value_by_ref("gay"));
+        assert_eq!(5, length.value_by_ref("girls"));
+        assert_eq!(3, length.value_by_ref("gay"));
+    }
+
+    /* References are handed out without cloning, even for values evicted
+     * as soon as they were computed.
+     */
+    #[test]
+    fn value_ref() {
+        #[derive(Debug, PartialEq)]
+        struct Uncloneable(u32);
+
+        let calls = std::cell::Cell::new(0);
+        let mut add_two = Memoizer::new(|n: u32| {
+            calls.set(calls.get() + 1);
+            Uncloneable(n + 2)
+        });
+        assert_eq!(&Uncloneable(4), add_two.value_ref(2));
+        assert_eq!(&Uncloneable(4), add_two.value_ref(2));
+        assert_eq!(1, calls.get());
+
+        let mut none = Memoizer::with_capacity_lru(0, |n: u32| Uncloneable(n + 2));
+        assert_eq!(&Uncloneable(4), none.value_ref(2));
+        assert!(none.store.map.is_empty());
+
+        // The spilled value goes once another one is computed
+        let mut one = Memoizer::with_capacity_lru(1, |n: u32| Uncloneable(n + 2));
+        assert_eq!(&Uncloneable(4), one.value_ref(2));
+        assert_eq!(&Uncloneable(5), one.value_ref(3));
+        assert!(one.store.spill.is_none());
+    }
+
+    /* Inserted values are bounded and expire like computed ones, and the
+     * policy forgets about removed ones.
+     */
+    #[test]
+    fn management() {
+        let calls = std::cell::Cell::new(0);
+        let mut add_two = Memoizer::with_capacity_lru(2, |n| {
+            calls.set(calls.get() + 1);
+            n + 2
+        });
+        assert_eq!(None, add_two.insert(1, 10));
+        assert!(add_two.prime(2, 20));
+        assert!(!add_two.prime(2, 0));
+        assert_eq!(10, add_two.value(1));
+
+        // Peeking doesn't count as a use, so 2 is still evicted first
+        assert_eq!(Some(&20), add_two.peek(&2));
+        add_two.insert(3, 30);
+        assert!(!add_two.contains(&2));
+        assert_eq!(0, calls.get());
+
+        assert_eq!(Some(10), add_two.invalidate(&1));
+        add_two.value(4);
+        assert!(add_two.contains(&3));
+        assert_eq!(2, add_two.len());
+
+        let mut keys = add_two.keys().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(vec![3, 4], keys);
+
+        add_two.retain(|&n, _| n != 3);
+        add_two.value(5);
+        assert!(add_two.contains(&4));
+        assert_eq!(vec![(4, 6)], add_two.drain().filter(|&(n, _)| n == 4).collect::<Vec<_>>());
+        assert!(add_two.is_empty());
+        add_two.value(6);
+        add_two.value(7);
+        assert_eq!(2, add_two.len());
+
+        // Expired values are left out and can be primed again
+        let clock = ManualClock::new();
+        let mut add_two = Memoizer::new(|n| n + 2)
+            .with_ttl(Duration::from_secs(10))
+            .with_clock(clock.clone());
+        add_two.insert(1, 10);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(None, add_two.peek(&1));
+        assert_eq!(0, add_two.iter().count());
+        assert_eq!(1, add_two.len());
+        assert!(add_two.prime(1, 11));
+        assert_eq!(11, add_two.value(1));
+
+        // The memory used goes down with the values
+        let mut words = Memoizer::new(|n: usize| "gay".repeat(n)).with_memory_budget(1 << 20);
+        words.value(1);
+        words.value(2);
+        words.retain(|&n, _| n == 1);
+        let one = words.memory_used();
+        words.invalidate(&1);
+        assert!(one > words.memory_used());
+        assert_eq!(Some(0), words.memory_used());
+        words.value(3);
+        words.clear();
+        assert_eq!(Some(0), words.memory_used());
+    }
+
+    /* Values stored behind an Arc or Rc are shared rather than cloned */
+    #[test]
+    fn shared() {
+        let mut squares = Memoizer::new(|n: u64| Arc::new(vec![n * n; 1000]));
+        let first = squares.value(3);
+        assert!(Arc::ptr_eq(&first, &squares.value(3)));
+        assert_eq!(2, Arc::strong_count(&first));
+
+        let mut words = Memoizer::with_capacity_lru(1, |n: usize| Rc::new("gay".repeat(n)));
+        let word = words.value(2);
+        words.value(3);
+        assert_eq!("gaygay", *word);
+        assert!(!Rc::ptr_eq(&word, &words.value(2)));
+        assert_eq!(1, Rc::strong_count(&word));
+    }
+
+    /* Values which took long to compute outlive cheap ones */
+    #[test]
+    fn gdsf_keeps_expensive() {
+        let calls = std::cell::Cell::new(0);
+        let mut slow_zero = Memoizer::with_capacity_gdsf(3, |n: u64| {
+            calls.set(calls.get() + 1);
+            if n == 0 {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            n + 2
+        });
+        slow_zero.value(0);
+        for n in 1..20 {
+            slow_zero.value(n);
+        }
+        assert_eq!(2, slow_zero.value(0));
+        assert_eq!(20, calls.get());
+    }
+
+    /* Frequently used values survive a scan of one-off keys */
+    #[test]
+    fn tiny_lfu_scan_resistance() {
+        fn misses<P: Policy<u32>>(policy: P) -> usize {
+            let calls = std::cell::Cell::new(0);
+            let mut add_two = Memoizer::with_policy(100, policy, |n| {
+                calls.set(calls.get() + 1);
+                n + 2
+            });
+            for _ in 0..5 {
+                for n in 0..50 {
+                    add_two.value(n);
+                }
+            }
+            for n in 1000..1200 {
+                add_two.value(n);
+            }
+
+            calls.set(0);
+            for n in 0..50 {
+                assert_eq!(n + 2, add_two.value(n));
+            }
+            assert_eq!(100, add_two.store.map.len());
+            calls.get()
+        }
+
+        assert_eq!(50, misses(Lru::new()));
+        assert!(misses(TinyLfu::new(100)) <= 5);
+        assert!(misses(AdaptiveReplacement::new(100)) <= 5);
+        assert!(misses(S3Fifo::new(100)) <= 5);
+    }
 }
@@ -0,0 +1,355 @@
+//! Caches for functions and methods which are memoized without a memoizer being passed around, by the `#[memoize]` attribute or by hand.
+
+// Imports
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread::LocalKey;
+use std::time::Duration;
+
+use crate::policy::Lru;
+use crate::store::Store;
+
+/// How often a memoized function found its value cached, and how many values it holds on to. Returned by the `<function>_cache_stats()` function generated by `#[memoize]` and by [`MethodMemoizer::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Calls answered from the cache.
+    pub hits: u64,
+    /// Calls which had to run the function.
+    pub misses: u64,
+    /// Values currently cached.
+    pub len: usize,
+}
+
+/// Memoizes a method in the instance it is called on. Kept in a field and given the method's arguments along with a closure computing the value, which can borrow `self` since it is only needed on a miss. The cache isn't locked while the closure runs, so the method can call itself.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::MethodMemoizer;
+/// struct Model {
+///     weights: Vec<f64>,
+///     scores: MethodMemoizer<u32, f64>,
+/// }
+///
+/// impl Model {
+///     fn score(&self, x: u32) -> f64 {
+///         self.scores.value(x, |x| self.weights.iter().map(|w| w * x as f64).sum())
+///     }
+/// }
+///
+/// let model = Model {
+///     weights: vec![0.5, 1.5],
+///     scores: MethodMemoizer::new(),
+/// };
+/// assert_eq!(4.0, model.score(2));
+/// assert_eq!(4.0, model.score(2));
+/// assert_eq!(1, model.scores.stats().hits);
+/// ```
+///
+#[derive(Debug)]
+pub struct MethodMemoizer<U, V>
+where
+    U: Eq + Hash + Clone,
+{
+    cache: Mutex<Cache<U, V>>,
+}
+
+impl<U, V> MethodMemoizer<U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Creates a new, unbounded MethodMemoizer.
+    pub fn new() -> MethodMemoizer<U, V> {
+        MethodMemoizer {
+            cache: Mutex::new(Cache::new(None, None)),
+        }
+    }
+
+    /// Creates a new MethodMemoizer which holds on to at most `capacity` values by evicting the least recently used one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::MethodMemoizer;
+    /// let squares = MethodMemoizer::with_capacity(1);
+    /// squares.value(2, |n: u64| n * n);
+    /// squares.value(3, |n: u64| n * n);
+    /// assert_eq!(1, squares.stats().len);
+    /// ```
+    ///
+    pub fn with_capacity(capacity: usize) -> MethodMemoizer<U, V> {
+        MethodMemoizer {
+            cache: Mutex::new(Cache::new(Some(capacity), None)),
+        }
+    }
+
+    /// Computes values again once `ttl` has passed since they were computed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::MethodMemoizer;
+    /// use std::time::Duration;
+    ///
+    /// let squares = MethodMemoizer::new().with_ttl(Duration::ZERO);
+    /// squares.value(2, |n: u64| n * n);
+    /// squares.value(2, |n: u64| n * n);
+    /// assert_eq!(2, squares.stats().misses);
+    /// ```
+    ///
+    pub fn with_ttl(self, ttl: Duration) -> MethodMemoizer<U, V> {
+        let mut cache = self
+            .cache
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        cache.store = cache.store.with_ttl(ttl);
+        MethodMemoizer {
+            cache: Mutex::new(cache),
+        }
+    }
+
+    /// Returns the value for the arguments, calling `compute` with them if it isn't cached yet.
+    pub fn value<G>(&self, key: U, compute: G) -> V
+    where
+        G: FnOnce(U) -> V,
+    {
+        shared(&self.cache, key, compute)
+    }
+
+    /// Removes every cached value and resets the counts.
+    pub fn clear(&self) {
+        lock(&self.cache).clear();
+    }
+
+    /// Returns the counts of hits and misses, and how many values are cached.
+    pub fn stats(&self) -> CacheStats {
+        lock(&self.cache).stats()
+    }
+}
+
+impl<U, V> Default for MethodMemoizer<U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn default() -> MethodMemoizer<U, V> {
+        MethodMemoizer::new()
+    }
+}
+
+/// A cache which is handed values computed elsewhere, keeping count of hits and misses. Values are computed by whoever asks for them without the cache borrowed, so that recursive functions can ask for more values while computing one.
+#[derive(Debug)]
+pub struct Cache<U, V>
+where
+    U: Eq + Hash + Clone,
+{
+    store: Store<U, V>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<U, V> Cache<U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Creates a cache, bounded by `capacity` and expiring values after `ttl` if they are given.
+    pub fn new(capacity: Option<usize>, ttl: Option<Duration>) -> Cache<U, V> {
+        let mut store = Store::new(capacity, Lru::new());
+        if let Some(ttl) = ttl {
+            store = store.with_ttl(ttl);
+        }
+        Cache {
+            store,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached value for the arguments, counting as a use of it.
+    pub fn get(&mut self, key: &U) -> Option<V> {
+        let value = self.store.get(key)?.clone();
+        self.hits += 1;
+        Some(value)
+    }
+
+    /// Caches a value computed after [`Cache::get`] missed.
+    pub fn insert(&mut self, key: U, value: V) {
+        self.misses += 1;
+        self.store.insert(key, value);
+    }
+
+    /// Removes every cached value and resets the counts.
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// Returns the counts of hits and misses, and how many values are cached.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.store.len(),
+        }
+    }
+
+    /// Returns the value for the arguments from a cache with one copy per thread, calling `compute` with them if it isn't cached yet.
+    pub fn local<G>(cache: &'static LocalKey<RefCell<Cache<U, V>>>, key: U, compute: G) -> V
+    where
+        G: FnOnce(U) -> V,
+    {
+        if let Some(value) = cache.with(|cache| cache.borrow_mut().get(&key)) {
+            return value;
+        }
+
+        let value = compute(key.clone());
+        cache.with(|cache| cache.borrow_mut().insert(key, value.clone()));
+        value
+    }
+}
+
+/// A cache shared between threads, created the first time it is used so that it can live in a `static`. Threads missing the same arguments at once each compute the value.
+#[derive(Debug)]
+pub struct Global<U, V>
+where
+    U: Eq + Hash + Clone,
+{
+    cache: OnceLock<Mutex<Cache<U, V>>>,
+    init: fn() -> Cache<U, V>,
+}
+
+impl<U, V> Global<U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Creates a cache which is set up by `init` when it is first used.
+    pub const fn new(init: fn() -> Cache<U, V>) -> Global<U, V> {
+        Global {
+            cache: OnceLock::new(),
+            init,
+        }
+    }
+
+    /// Locks the cache, setting it up if it hasn't been yet.
+    pub fn lock(&self) -> MutexGuard<'_, Cache<U, V>> {
+        lock(self.mutex())
+    }
+
+    /// Returns the value for the arguments, calling `compute` with them if it isn't cached yet.
+    pub fn value<G>(&self, key: U, compute: G) -> V
+    where
+        G: FnOnce(U) -> V,
+    {
+        shared(self.mutex(), key, compute)
+    }
+
+    fn mutex(&self) -> &Mutex<Cache<U, V>> {
+        self.cache.get_or_init(|| Mutex::new((self.init)()))
+    }
+}
+
+/* Looks a value up in a cache behind a lock, which is let go of while the
+ * value is computed on a miss.
+ */
+fn shared<U, V, G>(cache: &Mutex<Cache<U, V>>, key: U, compute: G) -> V
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    G: FnOnce(U) -> V,
+{
+    if let Some(value) = lock(cache).get(&key) {
+        return value;
+    }
+
+    let value = compute(key.clone());
+    lock(cache).insert(key, value.clone());
+    value
+}
+
+/* Nothing a panic could break is guarded by the lock */
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collatz(n: u64) -> u64 {
+        Cache::local(&CACHE, n, |n| match n {
+            1 => 0,
+            n if n % 2 == 0 => 1 + collatz(n / 2),
+            n => 1 + collatz(3 * n + 1),
+        })
+    }
+
+    thread_local!(static CACHE: RefCell<Cache<u64, u64>> = RefCell::new(Cache::new(None, None)));
+
+    /* Recursive calls ask the cache again while it isn't borrowed */
+    #[test]
+    fn recursive() {
+        assert_eq!(111, collatz(27));
+        assert_eq!(111, collatz(27));
+        let stats = CACHE.with(|cache| cache.borrow().stats());
+        assert_eq!(1, stats.hits);
+        assert_eq!(112, stats.misses);
+        assert_eq!(112, stats.len);
+
+        CACHE.with(|cache| cache.borrow_mut().clear());
+        assert_eq!(
+            CacheStats::default(),
+            CACHE.with(|cache| cache.borrow().stats())
+        );
+    }
+
+    static SQUARES: Global<u64, u64> = Global::new(|| Cache::new(Some(2), None));
+
+    /* The shared cache is bounded and counts calls from every thread */
+    #[test]
+    fn global() {
+        let square = |n| SQUARES.value(n, |n| n * n);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| assert_eq!(49, square(7)));
+            }
+        });
+        square(8);
+        square(9);
+        let stats = SQUARES.lock().stats();
+        assert_eq!(6, stats.hits + stats.misses);
+        assert_eq!(2, stats.len);
+    }
+
+    /* Methods can recurse through the memoizer in their own instance */
+    #[test]
+    fn method() {
+        struct Grid {
+            width: u64,
+            paths: MethodMemoizer<(u64, u64), u64>,
+        }
+
+        impl Grid {
+            fn paths(&self, x: u64, y: u64) -> u64 {
+                self.paths.value((x, y), |(x, y)| match (x, y) {
+                    (0, _) | (_, 0) => 1,
+                    (x, y) => self.paths(x - 1, y) + self.paths(x, y - 1),
+                })
+            }
+        }
+
+        let grid = Grid {
+            width: 16,
+            paths: MethodMemoizer::default(),
+        };
+        assert_eq!(601_080_390, grid.paths(grid.width, grid.width));
+        assert_eq!(17 * 17 - 1, grid.paths.stats().len);
+
+        grid.paths.clear();
+        assert_eq!(CacheStats::default(), grid.paths.stats());
+    }
+}
@@ -0,0 +1,266 @@
+//! Adaptive Replacement Cache eviction.
+
+// Imports
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use super::list::List;
+use super::{Cost, Policy};
+
+/// Adaptive Replacement Cache (ARC). Keys used once live in a recency list T1, keys used more than once in a frequency list T2. The keys most recently evicted from each are remembered in the ghost lists B1 and B2, and a miss on a ghost shifts the target size of T1 towards whichever list would have kept it. This lets the policy tune itself to workloads which move between favouring recency and favouring frequency.
+///
+/// The policy needs to know the capacity of the memoizer, so it should be created with the same one.
+#[derive(Debug, Clone)]
+pub struct AdaptiveReplacement<K> {
+    capacity: usize,
+    /// Target size of T1
+    target: usize,
+    lists: [List<K>; 4],
+    index: HashMap<K, (Segment, usize)>,
+    victims: VecDeque<K>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    T1 = 0,
+    T2 = 1,
+    B1 = 2,
+    B2 = 3,
+}
+
+impl<K> AdaptiveReplacement<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new ARC policy for a memoizer holding `capacity` values.
+    pub fn new(capacity: usize) -> AdaptiveReplacement<K> {
+        AdaptiveReplacement {
+            capacity,
+            target: 0,
+            lists: [List::new(), List::new(), List::new(), List::new()],
+            index: HashMap::new(),
+            victims: VecDeque::new(),
+        }
+    }
+
+    fn len(&self, segment: Segment) -> usize {
+        self.lists[segment as usize].len()
+    }
+
+    fn push(&mut self, segment: Segment, key: K) {
+        let node = self.lists[segment as usize].push_front(key.clone());
+        self.index.insert(key, (segment, node));
+    }
+
+    fn pop(&mut self, segment: Segment) -> Option<K> {
+        let key = self.lists[segment as usize].pop_back()?;
+        self.index.remove(&key);
+        Some(key)
+    }
+
+    fn unlink(&mut self, key: &K) -> Option<Segment> {
+        let (segment, node) = self.index.remove(key)?;
+        self.lists[segment as usize].remove(node);
+        Some(segment)
+    }
+
+    /* Queues a victim from T1 or T2 depending on the target size, remembering
+     * it in the matching ghost list.
+     */
+    fn replace(&mut self, hit_b2: bool) {
+        let t1 = self.len(Segment::T1);
+        let (from, ghost) = if t1 > 0
+            && (t1 > self.target || (hit_b2 && t1 == self.target) || self.len(Segment::T2) == 0)
+        {
+            (Segment::T1, Segment::B1)
+        } else {
+            (Segment::T2, Segment::B2)
+        };
+
+        if let Some(victim) = self.pop(from) {
+            // With no capacity there is nothing to tune, so no ghosts are kept
+            if self.capacity > 0 {
+                self.push(ghost, victim.clone());
+                self.forget();
+            }
+            self.victims.push_back(victim);
+        }
+    }
+
+    /* Drops the oldest ghosts to keep |T1| + |B1| <= c and
+     * |T1| + |T2| + |B1| + |B2| <= 2c, which evictions asked for outside of
+     * insert would otherwise break.
+     */
+    fn forget(&mut self) {
+        while self.len(Segment::T1) + self.len(Segment::B1) > self.capacity
+            && self.pop(Segment::B1).is_some()
+        {}
+        while self.resident() + self.len(Segment::B1) + self.len(Segment::B2)
+            > self.capacity.saturating_mul(2)
+            && (self.pop(Segment::B2).is_some() || self.pop(Segment::B1).is_some())
+        {}
+    }
+
+    fn resident(&self) -> usize {
+        self.len(Segment::T1) + self.len(Segment::T2)
+    }
+}
+
+impl<K> Policy<K> for AdaptiveReplacement<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if let Some(&(segment, node)) = self.index.get(key) {
+            if segment == Segment::T1 || segment == Segment::T2 {
+                let key = self.lists[segment as usize].remove(node);
+                self.push(Segment::T2, key);
+            }
+        }
+    }
+
+    fn insert(&mut self, key: &K, _: &Cost) {
+        self.victims.retain(|victim| victim != key);
+        let (b1, b2) = (self.len(Segment::B1), self.len(Segment::B2));
+        match self.index.get(key).map(|&(segment, _)| segment) {
+            Some(Segment::T1) | Some(Segment::T2) => self.touch(key),
+            Some(ghost) => {
+                // Would have been a hit had the list it was evicted from been
+                // bigger, so grow that list's share of the capacity
+                let hit_b2 = ghost == Segment::B2;
+                if hit_b2 {
+                    self.target = self.target.saturating_sub((b1 / b2).max(1));
+                } else {
+                    self.target = (self.target + (b2 / b1).max(1)).min(self.capacity);
+                }
+                self.unlink(key);
+                if self.resident() >= self.capacity {
+                    self.replace(hit_b2);
+                }
+                self.push(Segment::T2, key.clone());
+            }
+            None => {
+                let t1 = self.len(Segment::T1);
+                if t1 + b1 >= self.capacity {
+                    if t1 < self.capacity {
+                        self.pop(Segment::B1);
+                        self.replace(false);
+                    } else {
+                        let victim = self.pop(Segment::T1);
+                        self.victims.extend(victim);
+                    }
+                } else if self.resident() + b1 + b2 >= self.capacity {
+                    if self.resident() + b1 + b2 >= self.capacity * 2 {
+                        self.pop(Segment::B2);
+                    }
+                    if self.resident() >= self.capacity {
+                        self.replace(false);
+                    }
+                }
+                self.push(Segment::T1, key.clone());
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.unlink(key);
+        self.victims.retain(|victim| victim != key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        if self.victims.is_empty() {
+            self.replace(false);
+        }
+        self.victims.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* With no repeat uses it behaves like LRU */
+    #[test]
+    fn recency() {
+        let mut policy = AdaptiveReplacement::new(2);
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        policy.insert(&2, &Cost::default());
+        assert_eq!(Some(0), policy.evict());
+        policy.insert(&3, &Cost::default());
+        assert_eq!(Some(1), policy.evict());
+        assert_eq!(None, policy.victims.front());
+    }
+
+    /* Keys used twice move to T2 and outlive keys only used once */
+    #[test]
+    fn frequency() {
+        let mut policy = AdaptiveReplacement::new(3);
+        for key in 0..3 {
+            policy.insert(&key, &Cost::default());
+        }
+        policy.touch(&0);
+
+        for key in 10..20 {
+            policy.insert(&key, &Cost::default());
+            assert_ne!(Some(0), policy.evict());
+        }
+        assert_eq!(Segment::T2, policy.index[&0].0);
+    }
+
+    /* A miss on a ghost from T1 grows T1's target, one from T2 shrinks it */
+    #[test]
+    fn adapts() {
+        let mut policy = AdaptiveReplacement::new(2);
+        policy.insert(&0, &Cost::default());
+        policy.touch(&0);
+        policy.insert(&1, &Cost::default());
+        policy.insert(&2, &Cost::default());
+        assert_eq!(Some(1), policy.evict());
+        assert_eq!(Segment::B1, policy.index[&1].0);
+
+        policy.insert(&1, &Cost::default());
+        assert_eq!(1, policy.target);
+        assert_eq!(Some(0), policy.evict());
+        assert_eq!(Segment::B2, policy.index[&0].0);
+
+        policy.insert(&0, &Cost::default());
+        assert_eq!(0, policy.target);
+        assert_eq!(Some(2), policy.evict());
+        assert_eq!(Segment::T2, policy.index[&0].0);
+        assert_eq!(Segment::T2, policy.index[&1].0);
+    }
+
+    /* Ghosts stay within ARC's bounds, and none are kept with no capacity */
+    #[test]
+    fn bounded_ghosts() {
+        let mut none = crate::Memoizer::with_capacity_arc(0, |n: u32| n + 2);
+        let mut three = crate::Memoizer::with_capacity_arc(3, |n: u32| n + 2);
+        for n in 0..1000 {
+            none.value(n);
+            three.value(n % 7);
+            three.value(n);
+        }
+
+        let policy = &none.store.policy;
+        assert_eq!(0, policy.len(Segment::B1) + policy.len(Segment::B2));
+        assert!(policy.index.is_empty());
+
+        let policy = &three.store.policy;
+        assert!(policy.len(Segment::T1) + policy.len(Segment::B1) <= 3);
+        assert!(policy.index.len() <= 6);
+    }
+
+    /* Removed keys are forgotten, ghosts included */
+    #[test]
+    fn remove() {
+        let mut policy = AdaptiveReplacement::new(1);
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        assert_eq!(Some(0), policy.evict());
+        policy.remove(&0);
+        policy.remove(&1);
+        assert!(policy.index.is_empty());
+        assert_eq!(None, policy.evict());
+    }
+}
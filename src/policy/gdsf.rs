@@ -0,0 +1,205 @@
+//! GreedyDual-Size-Frequency eviction.
+
+// Imports
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+
+use super::{Cost, Policy};
+
+/// GreedyDual-Size-Frequency, which evicts the entries that are cheapest to recompute. Each entry gets a priority of how often it was used times how long its value took to compute, divided by its size when the memoizer has a memory budget, and the entry with the lowest priority is evicted first. So that entries which were expensive a long time ago don't stay forever, the priority of the last evicted entry is added to every entry inserted or used after it.
+///
+/// The memoizer times every call to the function for this policy, entries inserted without a time count as costing a nanosecond.
+#[derive(Debug, Clone)]
+pub struct Gdsf<K> {
+    /// Priority of the last evicted entry
+    clock: u64,
+    entries: HashMap<K, Priority>,
+    /// Lowest priority first, entries which have since been reprioritised or
+    /// removed are skipped when popped
+    queue: BinaryHeap<Reverse<(u64, u64)>>,
+    stamps: HashMap<u64, K>,
+    next_stamp: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Priority {
+    frequency: u64,
+    cost: u64,
+    size: u64,
+    stamp: u64,
+}
+
+impl<K> Gdsf<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new, empty GDSF policy.
+    pub fn new() -> Gdsf<K> {
+        Gdsf {
+            clock: 0,
+            entries: HashMap::new(),
+            queue: BinaryHeap::new(),
+            stamps: HashMap::new(),
+            next_stamp: 0,
+        }
+    }
+
+    /* Queues the key with a fresh priority, dropping its old place in the queue */
+    fn enqueue(&mut self, key: &K, frequency: u64, cost: u64, size: u64) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        if let Some(old) = self.entries.insert(
+            key.clone(),
+            Priority {
+                frequency,
+                cost,
+                size,
+                stamp,
+            },
+        ) {
+            self.stamps.remove(&old.stamp);
+        }
+        self.stamps.insert(stamp, key.clone());
+
+        let priority = self
+            .clock
+            .saturating_add(frequency.saturating_mul(cost) / size.max(1));
+        self.queue.push(Reverse((priority, stamp)));
+        if self.queue.len() > self.stamps.len() * 2 + 16 {
+            let stamps = &self.stamps;
+            self.queue
+                .retain(|Reverse((_, stamp))| stamps.contains_key(stamp));
+        }
+    }
+}
+
+impl<K> Default for Gdsf<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn default() -> Gdsf<K> {
+        Gdsf::new()
+    }
+}
+
+impl<K> Policy<K> for Gdsf<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if let Some(&Priority {
+            frequency,
+            cost,
+            size,
+            ..
+        }) = self.entries.get(key)
+        {
+            self.enqueue(key, frequency.saturating_add(1), cost, size);
+        }
+    }
+
+    fn insert(&mut self, key: &K, cost: &Cost) {
+        let size = cost.weight().unwrap_or(1) as u64;
+        let cost = cost.time().map_or(1, |time| time.as_nanos().max(1)) as u64;
+        let frequency = self.entries.get(key).map_or(0, |entry| entry.frequency);
+        self.enqueue(key, frequency.saturating_add(1), cost, size);
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.stamps.remove(&entry.stamp);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        while let Some(Reverse((priority, stamp))) = self.queue.pop() {
+            if let Some(key) = self.stamps.remove(&stamp) {
+                self.entries.remove(&key);
+                self.clock = priority;
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    fn timed(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cost(nanos: u64) -> Cost {
+        Cost::from_time(Duration::from_nanos(nanos))
+    }
+
+    /* The cheapest entry goes first, ties by age */
+    #[test]
+    fn cheapest_first() {
+        let mut policy = Gdsf::new();
+        policy.insert(&"slow", &cost(1000));
+        policy.insert(&"fast", &cost(10));
+        policy.insert(&"also fast", &cost(10));
+        assert_eq!(Some("fast"), policy.evict());
+        assert_eq!(Some("also fast"), policy.evict());
+        assert_eq!(Some("slow"), policy.evict());
+        assert_eq!(None, policy.evict());
+    }
+
+    /* Every use multiplies the cost */
+    #[test]
+    fn frequency() {
+        let mut policy = Gdsf::new();
+        policy.insert(&0, &cost(100));
+        policy.insert(&1, &cost(30));
+        for _ in 0..3 {
+            policy.touch(&1);
+        }
+        assert_eq!(Some(0), policy.evict());
+    }
+
+    /* Entries inserted after an eviction start from its priority */
+    #[test]
+    fn aging() {
+        let mut policy = Gdsf::new();
+        policy.insert(&0, &cost(100));
+        policy.insert(&1, &cost(60));
+        assert_eq!(Some(1), policy.evict());
+        policy.insert(&2, &cost(60));
+        assert_eq!(Some(0), policy.evict());
+        assert_eq!(120, policy.queue.peek().unwrap().0 .0);
+    }
+
+    /* Bigger entries go first when they took as long to compute */
+    #[test]
+    fn size() {
+        let mut policy = Gdsf::new();
+        policy.insert(&"small", &cost(1000).with_weight(10));
+        policy.insert(&"big", &cost(1000).with_weight(1000));
+        policy.insert(&"slow big", &cost(1_000_000).with_weight(1000));
+        assert_eq!(Some("big"), policy.evict());
+        assert_eq!(Some("small"), policy.evict());
+    }
+
+    /* Removed and reprioritised entries are skipped, and eventually dropped */
+    #[test]
+    fn remove() {
+        let mut policy = Gdsf::new();
+        for key in 0..100 {
+            policy.insert(&key, &cost(key + 1));
+            policy.touch(&key);
+        }
+        for key in 0..99 {
+            policy.remove(&key);
+        }
+        policy.insert(&100, &cost(1));
+        assert_eq!(2, policy.queue.len());
+        assert_eq!(Some(100), policy.evict());
+        assert_eq!(Some(99), policy.evict());
+        assert_eq!(None, policy.evict());
+    }
+}
@@ -0,0 +1,165 @@
+//! Doubly linked list backed by a vector, used to keep entries in order.
+
+/// Marks the absence of a node
+const NIL: usize = usize::MAX;
+
+/* Nodes are addressed by their index into the vector, which stays the same
+ * for as long as the node is in the list. Removed nodes are reused by later
+ * insertions, so every operation is O(1).
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct List<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    value: Option<T>,
+    prev: usize,
+    next: usize,
+}
+
+impl<T> List<T> {
+    pub(crate) fn new() -> List<T> {
+        List {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /* Least recently pushed node */
+    pub(crate) fn back(&self) -> Option<usize> {
+        Some(self.tail).filter(|&i| i != NIL)
+    }
+
+    /* Neighbour towards the front, the node pushed just after this one */
+    pub(crate) fn prev(&self, index: usize) -> Option<usize> {
+        Some(self.nodes[index].prev).filter(|&i| i != NIL)
+    }
+
+    pub(crate) fn get(&self, index: usize) -> &T {
+        self.nodes[index]
+            .value
+            .as_ref()
+            .expect("node is in the list")
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> &mut T {
+        self.nodes[index]
+            .value
+            .as_mut()
+            .expect("node is in the list")
+    }
+
+    pub(crate) fn push_front(&mut self, value: T) -> usize {
+        let node = Node {
+            value: Some(value),
+            prev: NIL,
+            next: NIL,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.link_front(index);
+        self.len += 1;
+        index
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        self.unlink(index);
+        self.free.push(index);
+        self.len -= 1;
+        self.nodes[index].value.take().expect("node is in the list")
+    }
+
+    pub(crate) fn pop_back(&mut self) -> Option<T> {
+        self.back().map(|index| self.remove(index))
+    }
+
+    pub(crate) fn move_to_front(&mut self, index: usize) {
+        if self.head != index {
+            self.unlink(index);
+            self.link_front(index);
+        }
+    }
+
+    fn link_front(&mut self, index: usize) {
+        self.nodes[index].prev = NIL;
+        self.nodes[index].next = self.head;
+        match self.head {
+            NIL => self.tail = index,
+            head => self.nodes[head].prev = index,
+        }
+        self.head = index;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let Node { prev, next, .. } = self.nodes[index];
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<T>(list: &mut List<T>) -> Vec<T> {
+        let mut values = Vec::new();
+        while let Some(value) = list.pop_back() {
+            values.push(value);
+        }
+        values
+    }
+
+    /* Nodes come off the back in the order they were pushed */
+    #[test]
+    fn fifo() {
+        let mut list = List::new();
+        for i in 0..4 {
+            list.push_front(i);
+        }
+        assert_eq!(4, list.len());
+        assert_eq!(vec![0, 1, 2, 3], drain(&mut list));
+        assert_eq!(0, list.len());
+        assert_eq!(None, list.pop_back());
+    }
+
+    /* Moving and removing nodes keeps the links intact */
+    #[test]
+    fn relink() {
+        let mut list = List::new();
+        let nodes: Vec<usize> = (0..4).map(|i| list.push_front(i)).collect();
+        list.move_to_front(nodes[0]);
+        assert_eq!(2, list.remove(nodes[2]));
+        list.move_to_front(nodes[1]);
+
+        // Removed slots are reused
+        assert_eq!(nodes[2], list.push_front(5));
+        assert_eq!(vec![3, 0, 1, 5], drain(&mut list));
+    }
+}
@@ -0,0 +1,106 @@
+//! Least recently used eviction.
+
+// Imports
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::list::List;
+use super::{Cost, Policy};
+
+/// Evicts the least recently used entry. Keys are kept in a linked list ordered by when they were last inserted or looked up, so every operation is O(1).
+#[derive(Debug, Clone)]
+pub struct Lru<K> {
+    list: List<K>,
+    index: HashMap<K, usize>,
+}
+
+impl<K> Lru<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new, empty LRU policy.
+    pub fn new() -> Lru<K> {
+        Lru {
+            list: List::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<K> Default for Lru<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn default() -> Lru<K> {
+        Lru::new()
+    }
+}
+
+impl<K> Policy<K> for Lru<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if let Some(&node) = self.index.get(key) {
+            self.list.move_to_front(node);
+        }
+    }
+
+    fn insert(&mut self, key: &K, _: &Cost) {
+        match self.index.get(key) {
+            Some(&node) => self.list.move_to_front(node),
+            None => {
+                let node = self.list.push_front(key.clone());
+                self.index.insert(key.clone(), node);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(node) = self.index.remove(key) {
+            self.list.remove(node);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let key = self.list.pop_back()?;
+        self.index.remove(&key);
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Evicts in order of last use */
+    #[test]
+    fn least_recently_used() {
+        let mut lru = Lru::new();
+        for key in 0..4 {
+            lru.insert(&key, &Cost::default());
+        }
+        lru.touch(&0);
+        lru.touch(&2);
+
+        assert_eq!(Some(1), lru.evict());
+        assert_eq!(Some(3), lru.evict());
+        assert_eq!(Some(0), lru.evict());
+        assert_eq!(Some(2), lru.evict());
+        assert_eq!(None, lru.evict());
+    }
+
+    /* Removed keys are never chosen, unknown keys are ignored */
+    #[test]
+    fn remove() {
+        let mut lru = Lru::new();
+        lru.insert(&"a", &Cost::default());
+        lru.insert(&"b", &Cost::default());
+        lru.remove(&"a");
+        lru.remove(&"z");
+        lru.touch(&"z");
+
+        assert_eq!(Some("b"), lru.evict());
+        assert_eq!(None, lru.evict());
+    }
+}
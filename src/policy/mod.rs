@@ -0,0 +1,74 @@
+//! Eviction policies for bounded memoizers.
+//!
+//! A [`Memoizer`](crate::Memoizer) created with a capacity keeps at most that many values, once it is over capacity its [`Policy`] chooses which ones to evict.
+
+// Imports
+use std::time::Duration;
+
+mod adaptive;
+mod gdsf;
+mod list;
+mod lru;
+mod s3_fifo;
+mod sieve;
+mod sketch;
+mod tiny_lfu;
+pub use adaptive::AdaptiveReplacement;
+pub use gdsf::Gdsf;
+pub use lru::Lru;
+pub use s3_fifo::S3Fifo;
+pub use sieve::Sieve;
+pub use tiny_lfu::TinyLfu;
+
+/// Decides which entries a bounded memoizer evicts. The memoizer tells the policy about every key it inserts, looks up and removes, and asks it for a victim whenever it is over capacity.
+pub trait Policy<K> {
+    /// Called when `key` is looked up and its value is found in the cache.
+    fn touch(&mut self, key: &K);
+
+    /// Called after the value for a new `key` has been inserted into the cache, along with what the value cost to compute.
+    fn insert(&mut self, key: &K, cost: &Cost);
+
+    /// Called when `key` is removed from the cache for any reason other than being evicted by this policy.
+    fn remove(&mut self, key: &K);
+
+    /// Chooses an entry to evict and stops tracking it. Returns None if there is nothing left to evict.
+    fn evict(&mut self) -> Option<K>;
+
+    /// Whether the memoizer should time each call to the function, so that [`Cost::time`] is known on insert. Policies which don't use it can leave this off to keep misses cheap.
+    fn timed(&self) -> bool {
+        false
+    }
+}
+
+/// What an entry cost to produce and what it costs to keep, passed to [`Policy::insert`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cost {
+    time: Option<Duration>,
+    weight: Option<usize>,
+}
+
+impl Cost {
+    /// Creates a cost for an entry which took `time` to compute.
+    pub fn from_time(time: Duration) -> Cost {
+        Cost {
+            time: Some(time),
+            weight: None,
+        }
+    }
+
+    /// Sets the estimated number of bytes the entry takes up.
+    pub fn with_weight(mut self, weight: usize) -> Cost {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// How long the function took to compute the value, if the policy asked for it to be timed.
+    pub fn time(&self) -> Option<Duration> {
+        self.time
+    }
+
+    /// The estimated number of bytes the entry takes up, if the memoizer has a memory budget.
+    pub fn weight(&self) -> Option<usize> {
+        self.weight
+    }
+}
@@ -0,0 +1,180 @@
+//! S3-FIFO eviction.
+
+// Imports
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::list::List;
+use super::{Cost, Policy};
+
+/// Caps the per entry hit counter
+const MAX_FREQUENCY: u8 = 3;
+
+/// S3-FIFO, built from three FIFO queues. New keys enter a small queue holding a tenth of the capacity, which quickly weeds out keys that are only used once. Keys hit while in the small queue move on to the main queue, where each hit buys them another pass through it. Keys evicted from the small queue are remembered in a ghost queue, so that if they come back they go straight to the main queue. Hits only bump a small counter, so they never reorder a queue.
+///
+/// The policy needs to know the capacity of the memoizer, so it should be created with the same one.
+#[derive(Debug, Clone)]
+pub struct S3Fifo<K> {
+    small: List<(K, u8)>,
+    main: List<(K, u8)>,
+    ghost: List<(K, u8)>,
+    index: HashMap<K, (Queue, usize)>,
+    small_capacity: usize,
+    main_capacity: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Queue {
+    Small,
+    Main,
+    Ghost,
+}
+
+impl<K> S3Fifo<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new S3-FIFO policy for a memoizer holding `capacity` values. A tenth of the capacity goes to the small queue, and the ghost queue remembers as many keys as the main queue holds.
+    pub fn new(capacity: usize) -> S3Fifo<K> {
+        let small_capacity = (capacity / 10).max(1);
+        S3Fifo {
+            small: List::new(),
+            main: List::new(),
+            ghost: List::new(),
+            index: HashMap::new(),
+            small_capacity,
+            main_capacity: capacity.saturating_sub(small_capacity),
+        }
+    }
+
+    fn list(&mut self, queue: Queue) -> &mut List<(K, u8)> {
+        match queue {
+            Queue::Small => &mut self.small,
+            Queue::Main => &mut self.main,
+            Queue::Ghost => &mut self.ghost,
+        }
+    }
+
+    fn push(&mut self, queue: Queue, key: K, frequency: u8) {
+        let node = self.list(queue).push_front((key.clone(), frequency));
+        self.index.insert(key, (queue, node));
+    }
+
+    fn pop(&mut self, queue: Queue) -> Option<(K, u8)> {
+        let (key, frequency) = self.list(queue).pop_back()?;
+        self.index.remove(&key);
+        Some((key, frequency))
+    }
+
+    fn remember(&mut self, key: K) {
+        if self.ghost.len() >= self.main_capacity {
+            self.pop(Queue::Ghost);
+        }
+        if self.main_capacity > 0 {
+            self.push(Queue::Ghost, key, 0);
+        }
+    }
+}
+
+impl<K> Policy<K> for S3Fifo<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if let Some(&(queue, node)) = self.index.get(key) {
+            if queue != Queue::Ghost {
+                let frequency = &mut self.list(queue).get_mut(node).1;
+                *frequency = (*frequency + 1).min(MAX_FREQUENCY);
+            }
+        }
+    }
+
+    fn insert(&mut self, key: &K, _: &Cost) {
+        match self.index.get(key) {
+            Some(&(Queue::Ghost, node)) => {
+                self.ghost.remove(node);
+                self.push(Queue::Main, key.clone(), 0);
+            }
+            Some(_) => self.touch(key),
+            None => self.push(Queue::Small, key.clone(), 0),
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((queue, node)) = self.index.remove(key) {
+            self.list(queue).remove(node);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        loop {
+            if self.small.len() > self.small_capacity || self.main.len() == 0 {
+                let (key, frequency) = self.pop(Queue::Small)?;
+                if frequency > 0 {
+                    self.push(Queue::Main, key, 0);
+                } else {
+                    self.remember(key.clone());
+                    return Some(key);
+                }
+            } else {
+                let (key, frequency) = self.pop(Queue::Main).expect("main queue is not empty");
+                if frequency > 0 {
+                    self.push(Queue::Main, key, frequency - 1);
+                } else {
+                    return Some(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Keys used once leave through the small queue, keys hit there move on */
+    #[test]
+    fn small_queue() {
+        let mut policy = S3Fifo::new(10);
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        policy.touch(&0);
+        policy.insert(&2, &Cost::default());
+
+        // 0 was hit so it moves to the main queue instead
+        assert_eq!(Some(1), policy.evict());
+        assert_eq!(Queue::Main, policy.index[&0].0);
+        assert_eq!(Queue::Ghost, policy.index[&1].0);
+    }
+
+    /* Keys coming back from the ghost queue skip the small queue */
+    #[test]
+    fn ghost() {
+        let mut policy = S3Fifo::new(10);
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        assert_eq!(Some(0), policy.evict());
+        policy.insert(&0, &Cost::default());
+        assert_eq!(Queue::Main, policy.index[&0].0);
+        assert_eq!(1, policy.small.len());
+    }
+
+    /* Each hit buys a key in the main queue another pass */
+    #[test]
+    fn main_queue() {
+        let mut policy = S3Fifo::new(10);
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        assert_eq!(Some(0), policy.evict());
+        policy.insert(&2, &Cost::default());
+        assert_eq!(Some(1), policy.evict());
+
+        policy.insert(&0, &Cost::default());
+        policy.insert(&1, &Cost::default());
+        policy.touch(&0);
+        assert_eq!(Some(1), policy.evict());
+        assert_eq!(Some(0), policy.evict());
+        assert_eq!(Some(2), policy.evict());
+        assert_eq!(None, policy.evict());
+    }
+}
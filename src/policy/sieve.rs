@@ -0,0 +1,138 @@
+//! SIEVE eviction.
+
+// Imports
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::list::List;
+use super::{Cost, Policy};
+
+/// SIEVE, a FIFO queue with a visited bit per entry. Hits only set the bit, so they never reorder the queue. To evict, a hand sweeps from the oldest entry towards the newest, clearing visited bits as it goes and evicting the first entry it finds unvisited. The hand remembers where it stopped, so the next eviction carries on from there.
+#[derive(Debug, Clone)]
+pub struct Sieve<K> {
+    list: List<(K, bool)>,
+    index: HashMap<K, usize>,
+    hand: Option<usize>,
+}
+
+impl<K> Sieve<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new, empty SIEVE policy.
+    pub fn new() -> Sieve<K> {
+        Sieve {
+            list: List::new(),
+            index: HashMap::new(),
+            hand: None,
+        }
+    }
+
+    fn unlink(&mut self, node: usize) -> K {
+        if self.hand == Some(node) {
+            self.hand = self.list.prev(node);
+        }
+        self.list.remove(node).0
+    }
+}
+
+impl<K> Default for Sieve<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn default() -> Sieve<K> {
+        Sieve::new()
+    }
+}
+
+impl<K> Policy<K> for Sieve<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        if let Some(&node) = self.index.get(key) {
+            self.list.get_mut(node).1 = true;
+        }
+    }
+
+    fn insert(&mut self, key: &K, _: &Cost) {
+        match self.index.get(key) {
+            Some(&node) => self.list.get_mut(node).1 = true,
+            None => {
+                let node = self.list.push_front((key.clone(), false));
+                self.index.insert(key.clone(), node);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(node) = self.index.remove(key) {
+            self.unlink(node);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let mut node = self.hand.or_else(|| self.list.back())?;
+        // Every entry is visited at most twice, once to clear its bit
+        loop {
+            let visited = &mut self.list.get_mut(node).1;
+            if !*visited {
+                break;
+            }
+            *visited = false;
+            node = self
+                .list
+                .prev(node)
+                .or_else(|| self.list.back())
+                .expect("list is not empty");
+        }
+
+        self.hand = Some(node);
+        let key = self.unlink(node);
+        self.index.remove(&key);
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Visited entries are skipped once, then evicted if not used again */
+    #[test]
+    fn second_chance() {
+        let mut policy = Sieve::new();
+        for key in 0..4 {
+            policy.insert(&key, &Cost::default());
+        }
+        policy.touch(&0);
+        policy.touch(&2);
+
+        assert_eq!(Some(1), policy.evict());
+        policy.insert(&4, &Cost::default());
+        policy.touch(&0);
+        // The hand carries on from where it stopped, skipping over 2
+        assert_eq!(Some(3), policy.evict());
+        assert_eq!(Some(4), policy.evict());
+        // Wraps around, 0 was touched again but 2 was not
+        assert_eq!(Some(2), policy.evict());
+        assert_eq!(Some(0), policy.evict());
+        assert_eq!(None, policy.evict());
+    }
+
+    /* Removing the entry under the hand moves the hand along */
+    #[test]
+    fn remove() {
+        let mut policy = Sieve::new();
+        for key in 0..4 {
+            policy.insert(&key, &Cost::default());
+        }
+        policy.touch(&1);
+        policy.touch(&2);
+        assert_eq!(Some(0), policy.evict());
+        policy.remove(&1);
+        policy.remove(&3);
+        assert_eq!(Some(2), policy.evict());
+        assert_eq!(None, policy.evict());
+    }
+}
@@ -0,0 +1,114 @@
+//! Count-Min Sketch estimating how often keys are used.
+
+// Imports
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of rows, each with its own hash of the key
+const DEPTH: usize = 4;
+/// Counters saturate at this value
+const MAX_COUNT: u8 = 15;
+
+/* Approximate frequency counts in a fixed amount of memory. Every key maps to
+ * one counter in each row and its frequency is the smallest of them, so
+ * collisions can only ever overestimate it. Once enough increments have been
+ * recorded every counter is halved, letting old popularity fade away.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Sketch {
+    hasher: RandomState,
+    counters: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl Sketch {
+    /* A sketch sized for tracking about `capacity` keys */
+    pub(crate) fn new(capacity: usize) -> Sketch {
+        let width = (capacity.max(16) * 4).next_power_of_two();
+        Sketch {
+            hasher: RandomState::new(),
+            counters: vec![0; width * DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: capacity.max(16) * 10,
+        }
+    }
+
+    pub(crate) fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .map(|i| self.counters[i])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn increment<K: Hash>(&mut self, key: &K) {
+        let mut incremented = false;
+        for i in self.indices(key) {
+            if self.counters[i] < MAX_COUNT {
+                self.counters[i] += 1;
+                incremented = true;
+            }
+        }
+
+        if incremented {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.age();
+            }
+        }
+    }
+
+    fn age(&mut self) {
+        for counter in &mut self.counters {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+
+    /* Index of the key's counter in each row, by double hashing */
+    fn indices<K: Hash>(&self, key: &K) -> impl Iterator<Item = usize> {
+        let hash = self.hasher.hash_one(key);
+        let (h1, h2) = (hash as usize, (hash >> 32) as usize | 1);
+        let width = self.mask + 1;
+        let mask = self.mask;
+        (0..DEPTH).map(move |row| row * width + (h1.wrapping_add(row.wrapping_mul(h2)) & mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Estimates never undercount, and saturate */
+    #[test]
+    fn frequency() {
+        let mut sketch = Sketch::new(100);
+        for _ in 0..3 {
+            sketch.increment(&"a");
+        }
+        sketch.increment(&"b");
+
+        assert!(sketch.frequency(&"a") >= 3);
+        assert!(sketch.frequency(&"b") >= 1);
+        for _ in 0..100 {
+            sketch.increment(&"a");
+        }
+        assert_eq!(MAX_COUNT, sketch.frequency(&"a"));
+    }
+
+    /* Counts are halved once the sample size is reached */
+    #[test]
+    fn aging() {
+        let mut sketch = Sketch::new(16);
+        for _ in 0..8 {
+            sketch.increment(&0);
+        }
+        let before = sketch.frequency(&0);
+        for key in 1..=sketch.sample_size {
+            sketch.increment(&key);
+        }
+        assert!(sketch.frequency(&0) < before);
+    }
+}
@@ -0,0 +1,216 @@
+//! Window TinyLFU admission and eviction.
+
+// Imports
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use super::list::List;
+use super::sketch::Sketch;
+use super::{Cost, Policy};
+
+/// Window TinyLFU, which copes well with skewed and scan heavy workloads. New keys enter a small LRU admission window. When a key falls out of the window it competes with the main region's next victim, and only the one used more often according to a frequency sketch stays. The main region is a segmented LRU: keys used again while on probation are promoted to a protected segment.
+///
+/// The policy needs to know the capacity of the memoizer, so it should be created with the same one.
+#[derive(Debug, Clone)]
+pub struct TinyLfu<K> {
+    sketch: Sketch,
+    window: List<K>,
+    probation: List<K>,
+    protected: List<K>,
+    index: HashMap<K, (Region, usize)>,
+    victims: VecDeque<K>,
+    window_capacity: usize,
+    protected_capacity: usize,
+    main_capacity: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Region {
+    Window,
+    Probation,
+    Protected,
+}
+
+impl<K> TinyLfu<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Creates a new TinyLFU policy for a memoizer holding `capacity` values. One percent of the capacity goes to the admission window, and eighty percent of the rest to the protected segment.
+    pub fn new(capacity: usize) -> TinyLfu<K> {
+        let window_capacity = (capacity / 100).max(1);
+        let main_capacity = capacity.saturating_sub(window_capacity);
+        TinyLfu {
+            sketch: Sketch::new(capacity),
+            window: List::new(),
+            probation: List::new(),
+            protected: List::new(),
+            index: HashMap::new(),
+            victims: VecDeque::new(),
+            window_capacity,
+            protected_capacity: main_capacity * 4 / 5,
+            main_capacity,
+        }
+    }
+
+    fn list(&mut self, region: Region) -> &mut List<K> {
+        match region {
+            Region::Window => &mut self.window,
+            Region::Probation => &mut self.probation,
+            Region::Protected => &mut self.protected,
+        }
+    }
+
+    fn push(&mut self, region: Region, key: K) {
+        let node = self.list(region).push_front(key.clone());
+        self.index.insert(key, (region, node));
+    }
+
+    fn pop(&mut self, region: Region) -> Option<K> {
+        let key = self.list(region).pop_back()?;
+        self.index.remove(&key);
+        Some(key)
+    }
+
+    /* Moves keys which overflowed the window into the main region, as long as
+     * they are used more often than the key they would displace.
+     */
+    fn admit(&mut self) {
+        while self.window.len() > self.window_capacity {
+            let candidate = self.pop(Region::Window).expect("window is not empty");
+            if self.probation.len() + self.protected.len() < self.main_capacity {
+                self.push(Region::Probation, candidate);
+                continue;
+            }
+
+            let region = if self.probation.len() > 0 {
+                Region::Probation
+            } else {
+                Region::Protected
+            };
+            let list = self.list_ref(region);
+            let victim = list
+                .back()
+                .map(|node| self.sketch.frequency(list.get(node)));
+            match victim {
+                Some(frequency) if self.sketch.frequency(&candidate) > frequency => {
+                    let victim = self.pop(region).expect("victim is in the list");
+                    self.victims.push_back(victim);
+                    self.push(Region::Probation, candidate);
+                }
+                _ => self.victims.push_back(candidate),
+            }
+        }
+    }
+
+    fn list_ref(&self, region: Region) -> &List<K> {
+        match region {
+            Region::Window => &self.window,
+            Region::Probation => &self.probation,
+            Region::Protected => &self.protected,
+        }
+    }
+}
+
+impl<K> Policy<K> for TinyLfu<K>
+where
+    K: Eq + Hash + Clone,
+{
+    fn touch(&mut self, key: &K) {
+        self.sketch.increment(key);
+        let (region, node) = match self.index.get(key) {
+            Some(&entry) => entry,
+            None => return,
+        };
+
+        match region {
+            Region::Window | Region::Protected => self.list(region).move_to_front(node),
+            Region::Probation => {
+                let key = self.probation.remove(node);
+                self.push(Region::Protected, key);
+                if self.protected.len() > self.protected_capacity {
+                    let demoted = self.pop(Region::Protected).expect("protected is not empty");
+                    self.push(Region::Probation, demoted);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, key: &K, _: &Cost) {
+        if self.index.contains_key(key) {
+            self.touch(key);
+            return;
+        }
+        self.victims.retain(|victim| victim != key);
+
+        self.sketch.increment(key);
+        self.push(Region::Window, key.clone());
+        self.admit();
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((region, node)) = self.index.remove(key) {
+            self.list(region).remove(node);
+        }
+        self.victims.retain(|victim| victim != key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        if let Some(victim) = self.victims.pop_front() {
+            return Some(victim);
+        }
+        self.pop(Region::Probation)
+            .or_else(|| self.pop(Region::Window))
+            .or_else(|| self.pop(Region::Protected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Keys only enter the main region if they are used more than its victim */
+    #[test]
+    fn admission() {
+        // Room for one key in the window and two in the main region
+        let mut policy = TinyLfu::new(3);
+        for key in 0..3 {
+            policy.insert(&key, &Cost::default());
+        }
+        for _ in 0..3 {
+            policy.touch(&0);
+            policy.touch(&1);
+        }
+        assert!(policy.victims.is_empty());
+
+        // A newcomer used once loses to everything in the main region
+        policy.insert(&3, &Cost::default());
+        assert_eq!(Some(2), policy.evict());
+        policy.insert(&4, &Cost::default());
+        assert_eq!(Some(3), policy.evict());
+
+        // Until it has been used more often than the main region's victim
+        for _ in 0..5 {
+            policy.touch(&4);
+        }
+        policy.insert(&5, &Cost::default());
+        assert_eq!(Some(0), policy.evict());
+        assert_eq!(Region::Probation, policy.index[&4].0);
+    }
+
+    /* Keys used again on probation are protected, pushing others back */
+    #[test]
+    fn segments() {
+        let mut policy = TinyLfu::new(200);
+        for key in 0..10 {
+            policy.insert(&key, &Cost::default());
+        }
+        policy.touch(&3);
+        assert_eq!(Region::Window, policy.index[&9].0);
+        assert_eq!(Region::Probation, policy.index[&0].0);
+        assert_eq!(Region::Protected, policy.index[&3].0);
+
+        policy.remove(&3);
+        assert!(!policy.index.contains_key(&3));
+        assert_eq!(Some(0), policy.evict());
+    }
+}
@@ -0,0 +1,49 @@
+//! Pseudo random numbers, used to spread out when memoized values expire.
+
+// Imports
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/* SplitMix64, seeded from the same randomness as HashMap. Plenty for jitter,
+ * nowhere near good enough for anything that needs to be unpredictable.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new() -> Rng {
+        Rng {
+            state: RandomState::new().build_hasher().finish(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /* Uniform in [0, 1) */
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Stays in range and isn't stuck */
+    #[test]
+    fn unit_interval() {
+        let mut rng = Rng::new();
+        let samples: Vec<f64> = (0..1000).map(|_| rng.next_f64()).collect();
+        assert!(samples.iter().all(|&x| (0.0..1.0).contains(&x)));
+        let mean = samples.iter().sum::<f64>() / 1000.0;
+        assert!((0.4..0.6).contains(&mean));
+    }
+}
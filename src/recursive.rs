@@ -0,0 +1,611 @@
+//! Memoization of recursive functions.
+
+// Imports
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::CycleError;
+
+/// Memoizes a recursive function. The function is handed a [`Recursion`] as its first argument, which it uses in place of calling itself so that every inner call is cached in the same map as the outer one.
+///
+/// Keys which are still being computed are tracked, so a function which depends on its own value is reported as a [`CycleError`] instead of recursing until the stack overflows. Alternatively a memoizer created with [`RecursiveMemoizer::tabled`] resolves such cycles to their least fixed point.
+#[derive(Debug)]
+pub struct RecursiveMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(&mut Recursion<'_, U, V>, U) -> V,
+{
+    function: F,
+    map: HashMap<U, V>,
+    stack: Vec<U>,
+    on_stack: HashMap<U, usize>,
+    tabling: Option<Tabling<U, V>>,
+}
+
+impl<U, V, F> RecursiveMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(&mut Recursion<'_, U, V>, U) -> V,
+{
+    /// Creates a new RecursiveMemoizer given a recursive function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::RecursiveMemoizer;
+    /// let mut fib = RecursiveMemoizer::new(|memo, n: u64| {
+    ///     if n < 2 {
+    ///         n
+    ///     } else {
+    ///         memo.value(n - 1) + memo.value(n - 2)
+    ///     }
+    /// });
+    /// assert_eq!(12_586_269_025, fib.value(50));
+    /// ```
+    ///
+    pub fn new(function: F) -> RecursiveMemoizer<U, V, F> {
+        RecursiveMemoizer {
+            function,
+            map: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashMap::new(),
+            tabling: None,
+        }
+    }
+
+    /// Returns the value for the memoized function, computing it (and any sub-problems it recurses into which have not been seen before) if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function depends on its own value and the memoizer is not tabled, see [`Recursion::value`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::RecursiveMemoizer;
+    /// // Number of monotonic paths through an n x m grid
+    /// let mut paths = RecursiveMemoizer::new(|memo, (n, m): (u64, u64)| {
+    ///     if n == 0 || m == 0 {
+    ///         1
+    ///     } else {
+    ///         memo.value((n - 1, m)) + memo.value((n, m - 1))
+    ///     }
+    /// });
+    /// assert_eq!(6, paths.value((2, 2)));
+    /// assert_eq!(184_756, paths.value((10, 10)));
+    /// ```
+    ///
+    pub fn value(&mut self, arg: U) -> V {
+        // Left over if a previous call unwound out of the function
+        self.stack.clear();
+        self.on_stack.clear();
+        if let Some(tabling) = &mut self.tabling {
+            tabling.reset();
+        }
+
+        Recursion {
+            function: &self.function,
+            map: &mut self.map,
+            stack: &mut self.stack,
+            on_stack: &mut self.on_stack,
+            tabling: &mut self.tabling,
+        }
+        .value(arg)
+    }
+}
+
+impl<U, V, F> RecursiveMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone + Eq,
+    F: Fn(&mut Recursion<'_, U, V>, U) -> V,
+{
+    /// Creates a new tabled RecursiveMemoizer given a bottom value and a recursive function which may depend on itself. A key requested while it is still being computed gets a provisional value, starting at `bottom`, and every key in the cycle is recomputed until none of their values change. Only then are they cached.
+    ///
+    /// This finds the least fixed point of the function as long as it is monotonic and its values cannot keep growing forever, as is the case for dataflow analyses, reachability or the nullable and first sets of a grammar. Otherwise it may never terminate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::RecursiveMemoizer;
+    /// use std::collections::BTreeSet;
+    ///
+    /// let edges = vec![vec![1], vec![2], vec![0, 3], vec![]];
+    /// let mut reachable = RecursiveMemoizer::tabled(BTreeSet::new(), |memo, n: usize| {
+    ///     let mut reached = BTreeSet::new();
+    ///     reached.insert(n);
+    ///     for &m in &edges[n] {
+    ///         reached.extend(memo.value(m));
+    ///     }
+    ///     reached
+    /// });
+    ///
+    /// assert_eq!(vec![0, 1, 2, 3], reachable.value(1).into_iter().collect::<Vec<_>>());
+    /// assert_eq!(vec![3], reachable.value(3).into_iter().collect::<Vec<_>>());
+    /// ```
+    ///
+    pub fn tabled(bottom: V, function: F) -> RecursiveMemoizer<U, V, F> {
+        RecursiveMemoizer {
+            tabling: Some(Tabling::new(bottom)),
+            ..RecursiveMemoizer::new(function)
+        }
+    }
+}
+
+/// Handle passed to the function of a [`RecursiveMemoizer`]. Recursive calls should go through [`Recursion::value`] so that they are memoized.
+pub struct Recursion<'a, U, V> {
+    function: &'a dyn Fn(&mut Recursion<'_, U, V>, U) -> V,
+    map: &'a mut HashMap<U, V>,
+    stack: &'a mut Vec<U>,
+    on_stack: &'a mut HashMap<U, usize>,
+    tabling: &'a mut Option<Tabling<U, V>>,
+}
+
+impl<'a, U, V> Recursion<'a, U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Returns the value of the memoized function for `arg`, calling back into the function only if it has not been computed before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `arg` is still being computed further up the call chain and the memoizer is not tabled. The message only says how long the cycle is, since keys needn't be `Debug`. Use [`Recursion::try_value`] to handle the cycle instead, its [`CycleError`] holds the chain of keys and prints them.
+    pub fn value(&mut self, arg: U) -> V {
+        self.try_value(arg).unwrap_or_else(|error| error.panic())
+    }
+
+    /// Returns the value of the memoized function for `arg`, or a [`CycleError`] describing the chain of dependencies if `arg` is still being computed further up the call chain. Tabled memoizers never return an error, they use the provisional value of `arg` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::RecursiveMemoizer;
+    /// // Follows pointers until it finds a node that points to itself
+    /// let next = [1, 2, 0, 3];
+    /// let mut root = RecursiveMemoizer::new(|memo, n: usize| match next[n] {
+    ///     m if m == n => Ok(n),
+    ///     m => memo.try_value(m).and_then(|root| root),
+    /// });
+    ///
+    /// assert_eq!(Ok(3), root.value(3));
+    /// assert_eq!(vec![0, 1, 2, 0], root.value(0).unwrap_err().into_chain());
+    /// ```
+    pub fn try_value(&mut self, arg: U) -> Result<V, CycleError<U>> {
+        if let Some(value) = self.map.get(&arg) {
+            return Ok(value.clone());
+        }
+        if let Some(&i) = self.on_stack.get(&arg) {
+            return match self.tabling {
+                Some(tabling) => Ok(tabling.read_in_progress(&arg, i)),
+                None => {
+                    let mut chain = self.stack[i..].to_vec();
+                    chain.push(arg);
+                    Err(CycleError::new(chain))
+                }
+            };
+        }
+        if let Some(tabling) = self.tabling {
+            if let Some(value) = tabling.read_provisional(&arg) {
+                return Ok(value);
+            }
+        }
+
+        self.on_stack.insert(arg.clone(), self.stack.len());
+        self.stack.push(arg.clone());
+
+        let value = if self.tabling.is_some() {
+            self.fixed_point(&arg)
+        } else {
+            let function = self.function;
+            let value = function(self, arg.clone());
+            self.map.insert(arg.clone(), value.clone());
+            value
+        };
+
+        self.stack.pop();
+        self.on_stack.remove(&arg);
+        Ok(value)
+    }
+
+    /* Computes the key on top of the stack for a tabled memoizer. If it turns
+     * out to be the first key of a cycle, the function is called again until
+     * the values of every key in the cycle stop changing.
+     */
+    fn fixed_point(&mut self, arg: &U) -> V {
+        let depth = self.stack.len() - 1;
+        let function = self.function;
+        loop {
+            let changes = self.tabling().begin(depth);
+            let value = function(self, arg.clone());
+
+            let tabling = self.tabling();
+            let low = tabling.end(arg, &value);
+            if low == usize::MAX {
+                // Did not depend on any unfinished cycles
+                self.map.insert(arg.clone(), value.clone());
+                return value;
+            } else if low < depth {
+                // Part of a cycle that started further up the stack
+                return value;
+            } else if tabling.changes == changes {
+                let members = tabling.finish(depth);
+                self.map.extend(members);
+                return value;
+            }
+        }
+    }
+
+    fn tabling(&mut self) -> &mut Tabling<U, V> {
+        self.tabling.as_mut().expect("memoizer is not tabled")
+    }
+}
+
+impl<'a, U, V> fmt::Debug for Recursion<'a, U, V>
+where
+    U: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recursion")
+            .field("map", &self.map)
+            .field("stack", &self.stack)
+            .finish()
+    }
+}
+
+/* Bookkeeping for tabled evaluation. Keys which belong to a cycle that is
+ * still being iterated get provisional values. Each provisional value is
+ * stamped with the time it was computed and the lowest frame it read an
+ * unfinished value from, so it can be reused for as long as that frame is on
+ * the same iteration.
+ */
+#[derive(Debug)]
+struct Tabling<U, V> {
+    bottom: V,
+    eq: fn(&V, &V) -> bool,
+    provisional: HashMap<U, (V, u64, usize)>,
+    frames: Vec<Frame>,
+    /// Keys given a provisional value, in order
+    members: Vec<U>,
+    time: u64,
+    changes: u64,
+}
+
+/* A key on the stack of a tabled memoizer */
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// Lowest frame read from while unfinished, usize::MAX if none
+    low: usize,
+    /// Time the current iteration started
+    start: u64,
+    /// Length of members when the first iteration started
+    mark: usize,
+}
+
+impl<U, V> Tabling<U, V>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn new(bottom: V) -> Tabling<U, V>
+    where
+        V: Eq,
+    {
+        Tabling {
+            bottom,
+            eq: V::eq,
+            provisional: HashMap::new(),
+            frames: Vec::new(),
+            members: Vec::new(),
+            time: 0,
+            changes: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.provisional.clear();
+        self.frames.clear();
+        self.members.clear();
+    }
+
+    /* Marks the frame on top of the stack as depending on frame `i` */
+    fn depends_on(&mut self, i: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.low = frame.low.min(i);
+        }
+    }
+
+    /* Value of a key which is on the stack at frame `i` */
+    fn read_in_progress(&mut self, key: &U, i: usize) -> V {
+        self.depends_on(i);
+        match self.provisional.get(key) {
+            Some((value, _, _)) => value.clone(),
+            None => self.bottom.clone(),
+        }
+    }
+
+    /* Value of a key computed during the current iteration of its cycle */
+    fn read_provisional(&mut self, key: &U) -> Option<V> {
+        let (value, time, low) = self.provisional.get(key)?;
+        if *time < self.frames.get(*low)?.start {
+            return None;
+        }
+
+        let (value, low) = (value.clone(), *low);
+        self.depends_on(low);
+        Some(value)
+    }
+
+    /* Starts an iteration of the frame at `depth`, returns the change count */
+    fn begin(&mut self, depth: usize) -> u64 {
+        self.time += 1;
+        let mark = match self.frames.get(depth) {
+            Some(frame) => frame.mark,
+            None => self.members.len(),
+        };
+        self.frames.truncate(depth);
+        self.frames.push(Frame {
+            low: usize::MAX,
+            start: self.time,
+            mark,
+        });
+        self.changes
+    }
+
+    /* Ends an iteration of the frame on top of the stack, recording its value
+     * if it read anything unfinished. Returns the lowest frame it read from.
+     */
+    fn end(&mut self, key: &U, value: &V) -> usize {
+        let depth = self.frames.len() - 1;
+        let low = self.frames[depth].low;
+        if low == usize::MAX {
+            self.frames.pop();
+            return low;
+        }
+
+        let previous = match self.provisional.get(key) {
+            Some((previous, _, _)) => previous,
+            None => &self.bottom,
+        };
+        if !(self.eq)(previous, value) {
+            self.changes += 1;
+        }
+        self.provisional
+            .insert(key.clone(), (value.clone(), self.time, low));
+        self.members.push(key.clone());
+
+        if low < depth {
+            self.frames.pop();
+            self.depends_on(low);
+        }
+        low
+    }
+
+    /* The cycle led by the frame at `depth` has converged. Removes it from
+     * the stack and returns the final values of its members.
+     */
+    fn finish(&mut self, depth: usize) -> Vec<(U, V)> {
+        let Frame { start, mark, .. } = self.frames[depth];
+        self.frames.truncate(depth);
+
+        let mut values = Vec::new();
+        for key in self.members.drain(mark..) {
+            // Members which were not reached in the last iteration are stale
+            if let Some((value, time, _)) = self.provisional.remove(&key) {
+                if time >= start {
+                    values.push((key, value));
+                }
+            }
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /* Fibonacci should only call the function once per sub-problem */
+    #[test]
+    fn fibonacci() {
+        let calls = Cell::new(0);
+        let mut fib = RecursiveMemoizer::new(|memo, n: u64| {
+            calls.set(calls.get() + 1);
+            if n < 2 {
+                n
+            } else {
+                memo.value(n - 1) + memo.value(n - 2)
+            }
+        });
+
+        assert_eq!(55, fib.value(10));
+        assert_eq!(11, calls.get());
+
+        // Everything up to 10 is already cached
+        assert_eq!(34, fib.value(9));
+        assert_eq!(89, fib.value(11));
+        assert_eq!(12, calls.get());
+    }
+
+    /* Classic two argument DP problem, packed into a tuple */
+    #[test]
+    fn edit_distance() {
+        let a: Vec<char> = "kitten".chars().collect();
+        let b: Vec<char> = "sitting".chars().collect();
+        let mut distance = RecursiveMemoizer::new(|memo, (i, j): (usize, usize)| {
+            if i == 0 {
+                j
+            } else if j == 0 {
+                i
+            } else {
+                let substitution: usize =
+                    memo.value((i - 1, j - 1)) + (a[i - 1] != b[j - 1]) as usize;
+                let deletion = memo.value((i - 1, j)) + 1;
+                let insertion = memo.value((i, j - 1)) + 1;
+                substitution.min(deletion).min(insertion)
+            }
+        });
+
+        assert_eq!(3, distance.value((a.len(), b.len())));
+    }
+
+    /* Heap allocated return values are cloned out of the map */
+    #[test]
+    fn heap_returned() {
+        let mut range = RecursiveMemoizer::new(|memo, n: usize| {
+            if n == 0 {
+                Vec::new()
+            } else {
+                let mut v: Vec<usize> = memo.value(n - 1);
+                v.push(n);
+                v
+            }
+        });
+
+        let mut v = range.value(3);
+        assert_eq!(vec![1, 2, 3], v);
+
+        v.push(23);
+        assert_eq!(vec![1, 2, 3], range.value(3));
+        assert_eq!(vec![1, 2], range.value(2));
+    }
+
+    /* Functions which can detect cycles return a Result of their own */
+    type Cyclic = Result<u32, CycleError<u32>>;
+
+    /* A key requested while it is being computed is reported with its chain */
+    #[test]
+    fn cycle_error() {
+        let mut acyclic = RecursiveMemoizer::new(|memo, n: u32| match n {
+            0 => Ok(0),
+            n => memo
+                .try_value((n + 1) % 4)
+                .and_then(|v: Cyclic| v)
+                .map(|v| v + 1),
+        });
+
+        assert_eq!(Ok(0), acyclic.value(0));
+        assert_eq!(Ok(3), acyclic.value(1));
+
+        let mut cyclic = RecursiveMemoizer::new(|memo, n: u32| {
+            memo.try_value(n % 3 + 1).and_then(|v: Cyclic| v)
+        });
+        let error = cyclic.value(1).unwrap_err();
+        assert_eq!(&[1, 2, 3, 1], error.chain());
+    }
+
+    /* Depending directly on yourself is a cycle of one */
+    #[test]
+    fn self_dependency() {
+        let mut cyclic =
+            RecursiveMemoizer::new(|memo, n: u32| memo.try_value(n).and_then(|v: Cyclic| v));
+        assert_eq!(vec![7, 7], cyclic.value(7).unwrap_err().into_chain());
+    }
+
+    /* Plain value() panics rather than recursing forever */
+    #[test]
+    #[should_panic(expected = "depends on itself through a cycle of 2 keys")]
+    fn cycle_panics() {
+        let mut cyclic = RecursiveMemoizer::new(|memo, n: u32| -> u32 { memo.value(1 - n) });
+        cyclic.value(0);
+    }
+
+    /* Reachability in a graph with cycles converges to the least fixed point */
+    #[test]
+    fn tabled_reachability() {
+        use std::collections::BTreeSet;
+
+        let edges: Vec<Vec<usize>> = vec![vec![1], vec![2, 4], vec![0, 3], vec![], vec![4]];
+        let calls = Cell::new(0);
+        let mut reachable = RecursiveMemoizer::tabled(BTreeSet::new(), |memo, n: usize| {
+            calls.set(calls.get() + 1);
+            let mut reached = BTreeSet::new();
+            reached.insert(n);
+            for &m in &edges[n] {
+                reached.extend(memo.value(m));
+            }
+            reached
+        });
+
+        let all: BTreeSet<usize> = (0..5).collect();
+        assert_eq!(all, reachable.value(0));
+        assert_eq!(all, reachable.value(1));
+        assert_eq!(all, reachable.value(2));
+        assert_eq!(vec![3], reachable.value(3).into_iter().collect::<Vec<_>>());
+        assert_eq!(vec![4], reachable.value(4).into_iter().collect::<Vec<_>>());
+
+        // Everything was cached by the first call
+        let after_first = calls.get();
+        reachable.value(1);
+        assert_eq!(after_first, calls.get());
+    }
+
+    /* Nullable non-terminals of a grammar, a boolean fixed point */
+    #[test]
+    fn tabled_nullable() {
+        // S -> A B | 'x',  A -> B | 'a',  B -> A | S | ''
+        // C -> C 'c',  D -> C | 'd'
+        let rules: Vec<(char, Vec<Vec<char>>)> = vec![
+            ('S', vec![vec!['A', 'B'], vec!['x']]),
+            ('A', vec![vec!['B'], vec!['a']]),
+            ('B', vec![vec!['A'], vec!['S'], vec![]]),
+            ('C', vec![vec!['C', 'c']]),
+            ('D', vec![vec!['C'], vec!['d']]),
+        ];
+        let mut nullable = RecursiveMemoizer::tabled(false, |memo, symbol: char| {
+            match rules.iter().find(|(lhs, _)| *lhs == symbol) {
+                Some((_, productions)) => productions
+                    .iter()
+                    .any(|rhs| rhs.iter().all(|&s| memo.value(s))),
+                None => false,
+            }
+        });
+
+        assert!(nullable.value('S'));
+        assert!(nullable.value('A'));
+        assert!(nullable.value('B'));
+        assert!(!nullable.value('C'));
+        assert!(!nullable.value('D'));
+        assert!(!nullable.value('x'));
+    }
+
+    /* Nested cycles which need several iterations to converge */
+    #[test]
+    fn tabled_nested_cycles() {
+        // Longest distance to 0 along edges, capped so the fixed point exists
+        let edges: Vec<Vec<usize>> = vec![vec![], vec![0, 2], vec![1, 3], vec![2, 1], vec![3]];
+        let mut distance = RecursiveMemoizer::tabled(0, |memo, n: usize| {
+            edges[n]
+                .iter()
+                .map(|&m| (memo.value(m) + 1).min(10))
+                .max()
+                .unwrap_or(0)
+        });
+
+        assert_eq!(10, distance.value(4));
+        assert_eq!(10, distance.value(1));
+        assert_eq!(0, distance.value(0));
+    }
+
+    /* Acyclic functions behave exactly like the untabled memoizer */
+    #[test]
+    fn tabled_acyclic() {
+        let calls = Cell::new(0);
+        let mut fib = RecursiveMemoizer::tabled(0, |memo, n: u64| {
+            calls.set(calls.get() + 1);
+            if n < 2 {
+                n
+            } else {
+                memo.value(n - 1) + memo.value(n - 2)
+            }
+        });
+
+        assert_eq!(55, fib.value(10));
+        assert_eq!(11, calls.get());
+    }
+}
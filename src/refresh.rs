@@ -0,0 +1,231 @@
+//! Recomputing memoized values in the background.
+
+// Imports
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// A task to be run in the background.
+pub type Task = Box<dyn FnOnce() + Send>;
+
+/// Runs tasks in the background, for memoizers which refresh values without making the caller wait. Implemented by [`Worker`], and by any closure taking a [`Task`], so that an existing thread pool or runtime can be used instead.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::{Memoizer, Task};
+/// use std::thread;
+///
+/// let add_two = Memoizer::new(|n: u64| n + 2).with_refresher(|task: Task| {
+///     thread::spawn(task);
+/// });
+/// ```
+///
+pub trait Spawn: Send + Sync {
+    /// Runs `task` in the background.
+    fn spawn(&self, task: Task);
+}
+
+impl<T> Spawn for T
+where
+    T: Fn(Task) + Send + Sync,
+{
+    fn spawn(&self, task: Task) {
+        self(task)
+    }
+}
+
+/// A single background thread which runs tasks one at a time, in the order they were spawned. The thread stops once the worker is dropped and it has run every task left. A task which panics doesn't take the thread down with it.
+///
+/// # Examples
+///
+/// ```
+///# use memoizer::{Memoizer, Worker};
+/// use std::time::Duration;
+///
+/// let mut add_two = Memoizer::new(|n: u64| n + 2)
+///     .with_ttl(Duration::from_secs(60))
+///     .with_stale_while_revalidate(Duration::from_secs(60))
+///     .with_refresher(Worker::new());
+/// assert_eq!(4, add_two.value(2));
+/// ```
+///
+#[derive(Debug)]
+pub struct Worker {
+    tasks: Sender<Task>,
+}
+
+impl Worker {
+    /// Starts a new worker thread.
+    pub fn new() -> Worker {
+        let (tasks, queue) = mpsc::channel::<Task>();
+        thread::spawn(move || {
+            for task in queue {
+                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+            }
+        });
+        Worker { tasks }
+    }
+}
+
+impl Default for Worker {
+    fn default() -> Worker {
+        Worker::new()
+    }
+}
+
+impl Spawn for Worker {
+    fn spawn(&self, task: Task) {
+        // The thread only goes away with the worker, a failed send just
+        // drops the task.
+        let _ = self.tasks.send(task);
+    }
+}
+
+/* Values refreshed in the background, None for ones whose task panicked or
+ * was dropped without being run.
+ */
+type Done<U, V> = Arc<Mutex<Vec<(U, Option<V>)>>>;
+
+/* Spawns refreshes of a memoizer's values and collects their results, making
+ * sure each key only has one refresh going at a time.
+ */
+pub(crate) struct Refresher<U, V> {
+    spawn: Box<dyn Fn(U, Done<U, V>) + Send + Sync>,
+    done: Done<U, V>,
+    pending: HashSet<U>,
+}
+
+impl<U, V> Refresher<U, V>
+where
+    U: Eq + Hash + Clone,
+{
+    pub(crate) fn new<F, S>(function: F, spawner: S) -> Refresher<U, V>
+    where
+        U: Send + 'static,
+        V: Send + 'static,
+        F: Fn(U) -> V + Clone + Send + Sync + 'static,
+        S: Spawn + 'static,
+    {
+        let spawn = move |key: U, done: Done<U, V>| {
+            let function = function.clone();
+            // Reports failure even if the task is dropped without running
+            let landing = Landing {
+                done,
+                key,
+                value: None,
+            };
+            spawner.spawn(Box::new(move || {
+                let mut landing = landing;
+                landing.value = Some(function(landing.key.clone()));
+            }))
+        };
+        Refresher {
+            spawn: Box::new(spawn),
+            done: Arc::new(Mutex::new(Vec::new())),
+            pending: HashSet::new(),
+        }
+    }
+
+    /* Starts refreshing `key` unless it already is being refreshed */
+    pub(crate) fn refresh(&mut self, key: &U) {
+        if self.pending.insert(key.clone()) {
+            (self.spawn)(key.clone(), Arc::clone(&self.done));
+        }
+    }
+
+    /* Takes the values refreshed since the last call */
+    pub(crate) fn finished(&mut self) -> Vec<(U, V)> {
+        let done = mem::take(&mut *lock(&self.done));
+        done.into_iter()
+            .filter_map(|(key, value)| {
+                self.pending.remove(&key);
+                Some((key, value?))
+            })
+            .collect()
+    }
+}
+
+impl<U, V> fmt::Debug for Refresher<U, V>
+where
+    U: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Refresher")
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+/* Held by a refresh task, reports its value or that it failed when dropped */
+struct Landing<U: Clone, V> {
+    done: Done<U, V>,
+    key: U,
+    value: Option<V>,
+}
+
+impl<U: Clone, V> Drop for Landing<U, V> {
+    fn drop(&mut self) {
+        lock(&self.done).push((self.key.clone(), self.value.take()));
+    }
+}
+
+/* Nothing a panic could break is guarded by the lock */
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /* Tasks run on the worker thread, even after one panics */
+    #[test]
+    fn worker() {
+        let worker = Worker::new();
+        let (sender, receiver) = mpsc::channel();
+        worker.spawn(Box::new(|| panic!("task fails")));
+        worker.spawn(Box::new(move || {
+            sender.send(thread::current().id()).unwrap()
+        }));
+        let id = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(thread::current().id(), id);
+    }
+
+    /* Each key is refreshed once at a time, failed refreshes can be retried */
+    #[test]
+    fn pending() {
+        let tasks: Arc<Mutex<Vec<Task>>> = Arc::default();
+        let queue = Arc::clone(&tasks);
+        let mut refresher = Refresher::new(
+            |n: u64| {
+                assert_ne!(0, n, "cannot refresh 0");
+                n + 2
+            },
+            move |task| lock(&queue).push(task),
+        );
+
+        refresher.refresh(&0);
+        refresher.refresh(&1);
+        refresher.refresh(&1);
+        assert_eq!(2, lock(&tasks).len());
+
+        for task in lock(&tasks).drain(..) {
+            let _ = panic::catch_unwind(AssertUnwindSafe(task));
+        }
+        assert_eq!(vec![(1, 3)], refresher.finished());
+        assert!(refresher.pending.is_empty());
+
+        // Dropped without running counts as failed too
+        refresher.refresh(&1);
+        lock(&tasks).clear();
+        assert_eq!(Vec::<(u64, u64)>::new(), refresher.finished());
+        assert!(refresher.pending.is_empty());
+    }
+}
@@ -0,0 +1,515 @@
+//! The values kept by a memoizer, along with everything deciding how long they are kept, apart from the function computing them.
+
+// Imports
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use crate::heap_size::{weigh, HeapSize};
+use crate::policy::{Cost, Lru, Policy};
+use crate::random::Rng;
+use crate::refresh::Refresher;
+use crate::{Clock, SystemClock};
+
+/* Cached values, their eviction policy, expiry and memory accounting. Handed
+ * values computed by whoever owns it, so that memoizers calling their
+ * functions differently can all keep their values the same way.
+ */
+#[derive(Debug)]
+pub(crate) struct Store<U, V, P = Lru<U>>
+where
+    U: Eq + Hash + Clone,
+    P: Policy<U>,
+{
+    pub(crate) map: HashMap<U, Slot<V>>,
+    pub(crate) spill: Option<V>,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) policy: P,
+    capacity: Option<usize>,
+    budget: Option<usize>,
+    weigher: Option<fn(&U, &V) -> usize>,
+    weight: usize,
+    ttl: Option<Duration>,
+    expiry: Option<fn(&U, &V) -> Option<Duration>>,
+    refresher: Option<Refresher<U, V>>,
+    stale: Option<Duration>,
+    ahead: Option<Duration>,
+    beta: Option<f64>,
+    jitter: Option<f64>,
+    rng: Rng,
+}
+
+/* A memoized value, when it goes stale if ever, how long it took to compute
+ * and how many bytes it takes up if those were measured.
+ */
+#[derive(Debug)]
+pub(crate) struct Slot<V> {
+    pub(crate) value: V,
+    expires: Option<Instant>,
+    delta: Duration,
+    weight: usize,
+}
+
+impl<V> Slot<V> {
+    fn expired(&self, now: Option<Instant>) -> bool {
+        match (self.expires, now) {
+            (Some(expires), Some(now)) => now >= expires,
+            _ => false,
+        }
+    }
+}
+
+impl<U, V, P> Store<U, V, P>
+where
+    U: Eq + Hash + Clone,
+    P: Policy<U>,
+{
+    /* Keeps at most `capacity` values, if given, evicting the ones chosen by
+     * `policy`.
+     */
+    pub(crate) fn new(capacity: Option<usize>, policy: P) -> Store<U, V, P> {
+        Store {
+            map: HashMap::new(),
+            spill: None,
+            clock: Box::new(SystemClock),
+            policy,
+            capacity,
+            budget: None,
+            weigher: None,
+            weight: 0,
+            ttl: None,
+            expiry: None,
+            refresher: None,
+            stale: None,
+            ahead: None,
+            beta: None,
+            jitter: None,
+            rng: Rng::new(),
+        }
+    }
+
+    pub(crate) fn with_ttl(mut self, ttl: Duration) -> Store<U, V, P> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub(crate) fn with_expiry(mut self, expiry: fn(&U, &V) -> Option<Duration>) -> Store<U, V, P> {
+        self.expiry = Some(expiry);
+        self
+    }
+
+    pub(crate) fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Store<U, V, P> {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub(crate) fn with_stale_while_revalidate(mut self, stale: Duration) -> Store<U, V, P> {
+        self.stale = Some(stale);
+        self
+    }
+
+    pub(crate) fn with_refresh_ahead(mut self, ahead: Duration) -> Store<U, V, P> {
+        self.ahead = Some(ahead);
+        self
+    }
+
+    pub(crate) fn with_refresher(mut self, refresher: Refresher<U, V>) -> Store<U, V, P> {
+        self.refresher = Some(refresher);
+        self
+    }
+
+    pub(crate) fn with_early_expiration(mut self, beta: f64) -> Store<U, V, P> {
+        assert!(
+            beta.is_finite() && beta >= 0.0,
+            "early expiration beta must be finite and non-negative"
+        );
+        self.beta = Some(beta);
+        self
+    }
+
+    pub(crate) fn with_ttl_jitter(mut self, fraction: f64) -> Store<U, V, P> {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "ttl jitter must be a fraction between 0 and 1"
+        );
+        self.jitter = Some(fraction);
+        self
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    pub(crate) fn memory_budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    pub(crate) fn memory_used(&self) -> Option<usize> {
+        self.weigher.map(|_| self.weight)
+    }
+
+    /* Returns the cached value for the key, calling `function` to compute it
+     * if there isn't one.
+     */
+    pub(crate) fn value_ref<G>(&mut self, arg: U, function: G) -> &V
+    where
+        G: FnOnce(U) -> V,
+    {
+        if self.cached(&arg) {
+            return &self.map[&arg].value;
+        }
+        self.compute(arg, function)
+    }
+
+    /* Returns the cached value for the key, counting as a use of it */
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.cached(key) {
+            return None;
+        }
+        self.map.get(key).map(|slot| &slot.value)
+    }
+
+    /* Returns whether the cached value for the key is fresh enough to serve,
+     * dropping it if it has expired.
+     */
+    pub(crate) fn cached<Q>(&mut self, key: &Q) -> bool
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.refresher.is_some() {
+            self.land_refreshes();
+        }
+
+        let now = self.now();
+        let bounded = self.bounded();
+        let (arg, slot) = match self.map.get_key_value(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let mut expired = slot.expired(now);
+        if let (Some(beta), Some(now), false) = (self.beta, now, expired) {
+            // XFetch, expire early with a chance that grows as the expiry
+            // gets closer and the longer the value took to compute. A gap
+            // too long to count expires the value now.
+            let scale = beta * -(1.0 - self.rng.next_f64()).ln();
+            let gap = Duration::try_from_secs_f64(slot.delta.as_secs_f64() * scale);
+            expired = slot.expires.is_some()
+                && gap
+                    .ok()
+                    .and_then(|gap| now.checked_add(gap))
+                    .is_none_or(|later| slot.expired(Some(later)));
+        }
+
+        // Stale values can still be served while they are refreshed
+        let mut serve = !expired;
+        if let Some(refresher) = &mut self.refresher {
+            let stale = self
+                .stale
+                .is_some_and(|stale| !slot.expired(now.and_then(|now| now.checked_sub(stale))));
+            let ahead = self
+                .ahead
+                .is_some_and(|ahead| slot.expired(now.and_then(|now| now.checked_add(ahead))));
+            if (expired && stale) || (!expired && ahead) {
+                serve = true;
+                refresher.refresh(arg);
+            }
+        }
+
+        if serve {
+            if bounded {
+                self.policy.touch(arg);
+            }
+            return true;
+        }
+        if let Some((arg, _)) = self.discard(key) {
+            if bounded {
+                self.policy.remove(&arg);
+            }
+        }
+        false
+    }
+
+    /* Calls the function for a key which isn't cached and caches the value */
+    pub(crate) fn compute<G>(&mut self, arg: U, function: G) -> &V
+    where
+        G: FnOnce(U) -> V,
+    {
+        let (value, delta) = self.time(|| function(arg.clone()));
+        self.cache(arg, value, delta)
+    }
+
+    /* Makes a call to the function, timing it if early expiration or the
+     * policy need to know how long it took.
+     */
+    pub(crate) fn time<T, G>(&self, call: G) -> (T, Option<Duration>)
+    where
+        G: FnOnce() -> T,
+    {
+        if self.beta.is_some() || (self.bounded() && self.policy.timed()) {
+            let start = Instant::now();
+            let value = call();
+            (value, Some(start.elapsed()))
+        } else {
+            (call(), None)
+        }
+    }
+
+    /* Caches a value for a key which isn't cached. Should the value be evicted
+     * straight away it is kept in the spill slot instead, so that there is
+     * always something to return a reference to.
+     */
+    pub(crate) fn cache(&mut self, arg: U, value: V, delta: Option<Duration>) -> &V {
+        let slot = self.slot(&arg, value, delta.unwrap_or_default());
+        self.spill = None;
+        if !self.bounded() {
+            return &self.map.entry(arg).or_insert(slot).value;
+        }
+
+        self.admit(arg.clone(), slot, delta);
+        self.shrink(Some(&arg));
+        match self.map.get(&arg) {
+            Some(slot) => &slot.value,
+            None => self.spill.as_ref().expect("evicted value is spilled"),
+        }
+    }
+
+    pub(crate) fn purge_expired(&mut self) -> usize {
+        let now = self.now();
+        if now.is_none() {
+            return 0;
+        }
+
+        let before = self.map.len();
+        let bounded = self.bounded();
+        let policy = &mut self.policy;
+        let weight = &mut self.weight;
+        self.map.retain(|key, slot| {
+            let expired = slot.expired(now);
+            if expired {
+                *weight -= slot.weight;
+                if bounded {
+                    policy.remove(key);
+                }
+            }
+            !expired
+        });
+        before - self.map.len()
+    }
+
+    pub(crate) fn contains<Q>(&self, key: &Q) -> bool
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    pub(crate) fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.now();
+        self.map
+            .get(key)
+            .filter(|slot| !slot.expired(now))
+            .map(|slot| &slot.value)
+    }
+
+    pub(crate) fn insert(&mut self, key: U, value: V) -> Option<V> {
+        let slot = self.slot(&key, value, Duration::ZERO);
+        let old = self.admit(key, slot, None);
+        self.shrink(None);
+        old.map(|old| old.value)
+    }
+
+    pub(crate) fn prime(&mut self, key: U, value: V) -> bool {
+        if self.contains(&key) {
+            return false;
+        }
+        self.insert(key, value);
+        true
+    }
+
+    pub(crate) fn invalidate<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, slot) = self.discard(key)?;
+        if self.bounded() {
+            self.policy.remove(&key);
+        }
+        Some(slot.value)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        drop(self.drain());
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&U, &V)> + '_ {
+        let now = self.now();
+        self.map
+            .iter()
+            .filter(move |(_, slot)| !slot.expired(now))
+            .map(|(key, slot)| (key, &slot.value))
+    }
+
+    pub(crate) fn retain<R>(&mut self, mut keep: R)
+    where
+        R: FnMut(&U, &V) -> bool,
+    {
+        let bounded = self.bounded();
+        let policy = &mut self.policy;
+        let weight = &mut self.weight;
+        self.map.retain(|key, slot| {
+            let kept = keep(key, &slot.value);
+            if !kept {
+                *weight -= slot.weight;
+                if bounded {
+                    policy.remove(key);
+                }
+            }
+            kept
+        });
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (U, V)> + '_ {
+        if self.bounded() {
+            for key in self.map.keys() {
+                self.policy.remove(key);
+            }
+        }
+        self.weight = 0;
+        self.spill = None;
+        self.map.drain().map(|(key, slot)| (key, slot.value))
+    }
+
+    /* Only reads the clock if values can expire */
+    pub(crate) fn now(&self) -> Option<Instant> {
+        if self.ttl.is_some() || self.expiry.is_some() {
+            Some(self.clock.now())
+        } else {
+            None
+        }
+    }
+
+    /* Replaces the values of keys refreshed in the background, unless they
+     * were removed in the meantime.
+     */
+    fn land_refreshes(&mut self) {
+        let finished = match &mut self.refresher {
+            Some(refresher) => refresher.finished(),
+            None => return,
+        };
+        for (key, value) in finished {
+            if let Some(delta) = self.map.get(&key).map(|slot| slot.delta) {
+                let slot = self.slot(&key, value, delta);
+                self.store(key, slot);
+            }
+        }
+        // A refreshed value can weigh more than the one it replaced
+        self.shrink(None);
+    }
+
+    /* Whether the policy is keeping track of the keys */
+    fn bounded(&self) -> bool {
+        self.capacity.is_some() || self.budget.is_some()
+    }
+
+    /* Lets the policy know about a new value, if it is keeping track, and
+     * stores it without making room for it yet.
+     */
+    fn admit(&mut self, key: U, slot: Slot<V>, delta: Option<Duration>) -> Option<Slot<V>> {
+        if self.bounded() {
+            let mut cost = delta.map_or_else(Cost::default, Cost::from_time);
+            if self.weigher.is_some() {
+                cost = cost.with_weight(slot.weight);
+            }
+            self.policy.insert(&key, &cost);
+        }
+        self.store(key, slot)
+    }
+
+    fn store(&mut self, key: U, slot: Slot<V>) -> Option<Slot<V>> {
+        self.weight += slot.weight;
+        let old = self.map.insert(key, slot)?;
+        self.weight -= old.weight;
+        Some(old)
+    }
+
+    fn discard<Q>(&mut self, key: &Q) -> Option<(U, Slot<V>)>
+    where
+        U: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, slot) = self.map.remove_entry(key)?;
+        self.weight -= slot.weight;
+        Some((key, slot))
+    }
+
+    /* Evicts until back within the capacity and memory budget, spilling the
+     * value for `arg` if it gets evicted.
+     */
+    fn shrink(&mut self, arg: Option<&U>) {
+        while self
+            .capacity
+            .is_some_and(|capacity| self.map.len() > capacity)
+            || self.budget.is_some_and(|budget| self.weight > budget)
+        {
+            let (key, slot) = match self.policy.evict() {
+                Some(key) => match self.discard(&key) {
+                    Some(entry) => entry,
+                    None => continue,
+                },
+                None => break,
+            };
+            if Some(&key) == arg {
+                self.spill = Some(slot.value);
+            }
+        }
+    }
+
+    fn slot(&mut self, arg: &U, value: V, delta: Duration) -> Slot<V> {
+        let mut ttl = self
+            .expiry
+            .and_then(|expiry| expiry(arg, &value))
+            .or(self.ttl);
+        if let (Some(jitter), Some(duration)) = (self.jitter, ttl) {
+            ttl = Some(duration.mul_f64(1.0 - jitter * self.rng.next_f64()));
+        }
+        Slot {
+            expires: ttl.and_then(|ttl| self.now()?.checked_add(ttl)),
+            weight: self.weigher.map_or(0, |weigher| weigher(arg, &value)),
+            value,
+            delta,
+        }
+    }
+}
+
+impl<U, V, P> Store<U, V, P>
+where
+    U: Eq + Hash + Clone + HeapSize,
+    V: HeapSize,
+    P: Policy<U>,
+{
+    pub(crate) fn with_memory_budget(mut self, bytes: usize) -> Store<U, V, P> {
+        self.budget = Some(bytes);
+        self.weigher = Some(weigh::<U, V>);
+        self
+    }
+}
@@ -0,0 +1,375 @@
+//! Memoization which can be shared between threads.
+
+// Imports
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+
+/// A memoizer which can be shared between threads. Values are looked up from `&self`, the map is split into shards which are each behind a read-write lock, so cache hits can happen concurrently and only inserting a new value locks a single shard.
+///
+/// Misses are deduplicated: while one thread is computing the value for a key, any other thread asking for the same key waits for that result instead of calling the function again.
+///
+/// The memoizer is `Sync` as long as the function is `Sync` and the keys and values are `Send + Sync`.
+#[derive(Debug)]
+pub struct SyncMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> V,
+{
+    function: F,
+    hasher: RandomState,
+    shards: Box<[Shard<U, V>]>,
+}
+
+impl<U, V, F> SyncMemoizer<U, V, F>
+where
+    U: Eq + Hash + Clone,
+    V: Clone,
+    F: Fn(U) -> V,
+{
+    /// Creates a new SyncMemoizer given a function, with a few shards for each thread the machine can run in parallel.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::SyncMemoizer;
+    /// use std::thread;
+    ///
+    /// let square = SyncMemoizer::new(|n: u64| n * n);
+    /// thread::scope(|s| {
+    ///     for _ in 0..4 {
+    ///         s.spawn(|| assert_eq!(49, square.value(7)));
+    ///     }
+    /// });
+    /// ```
+    ///
+    pub fn new(function: F) -> SyncMemoizer<U, V, F> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        SyncMemoizer::with_shards(threads * 4, function)
+    }
+
+    /// Creates a new SyncMemoizer given a function and the number of shards to split the map into. More shards means less contention between threads inserting values at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::SyncMemoizer;
+    /// let add_two = SyncMemoizer::with_shards(16, |n| n + 2);
+    /// assert_eq!(4, add_two.value(2));
+    /// ```
+    ///
+    pub fn with_shards(shards: usize, function: F) -> SyncMemoizer<U, V, F> {
+        assert!(shards > 0, "SyncMemoizer needs at least one shard");
+        SyncMemoizer {
+            function,
+            hasher: RandomState::new(),
+            shards: (0..shards).map(|_| Shard::new()).collect(),
+        }
+    }
+
+    /// Returns the value for the memoized function. If the function has already been called before, it will use the previous value. If another thread is already computing the value for `arg`, this blocks until it is done and returns the same value. Should that thread panic, one of the waiting threads takes over computing the value.
+    ///
+    /// The function is called without holding any locks. It must not ask the same memoizer for the key it is computing, as it would wait on itself forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///# use memoizer::SyncMemoizer;
+    /// let length = SyncMemoizer::new(|s: String| s.len());
+    /// assert_eq!(8, length.value(String::from("gaygirls")));
+    /// assert_eq!(8, length.value(String::from("gaygirls")));
+    /// ```
+    ///
+    pub fn value(&self, arg: U) -> V {
+        let shard = &self.shards[self.shard(&arg)];
+        loop {
+            if let Some(value) = shard.get(&arg) {
+                return value;
+            }
+
+            let (flight, leader) = {
+                let mut in_flight = lock(&shard.in_flight);
+                // The leader caches its value before leaving in_flight
+                if let Some(value) = shard.get(&arg) {
+                    return value;
+                }
+                match in_flight.get(&arg) {
+                    Some(flight) => (Arc::clone(flight), false),
+                    None => {
+                        let flight = Arc::new(Flight::new());
+                        in_flight.insert(arg.clone(), Arc::clone(&flight));
+                        (flight, true)
+                    }
+                }
+            };
+
+            if !leader {
+                match flight.wait() {
+                    Some(value) => return value,
+                    // The leader panicked, try again
+                    None => continue,
+                }
+            }
+
+            let mut guard = Landing {
+                shard,
+                key: &arg,
+                flight: &flight,
+                value: None,
+            };
+            let value = (self.function)(arg.clone());
+            shard
+                .map
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(arg.clone(), value.clone());
+            guard.value = Some(value.clone());
+            return value;
+        }
+    }
+
+    fn shard(&self, arg: &U) -> usize {
+        (self.hasher.hash_one(arg) % self.shards.len() as u64) as usize
+    }
+}
+
+/* A slice of the map, along with the keys currently being computed in it */
+#[derive(Debug)]
+struct Shard<U, V> {
+    map: RwLock<HashMap<U, V>>,
+    in_flight: Mutex<HashMap<U, Arc<Flight<V>>>>,
+}
+
+impl<U, V> Shard<U, V>
+where
+    U: Eq + Hash,
+    V: Clone,
+{
+    fn new() -> Shard<U, V> {
+        Shard {
+            map: RwLock::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, arg: &U) -> Option<V> {
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(arg)
+            .cloned()
+    }
+}
+
+/* A value being computed by one thread that others can wait on */
+#[derive(Debug)]
+struct Flight<V> {
+    state: Mutex<FlightState<V>>,
+    landed: Condvar,
+}
+
+#[derive(Debug)]
+enum FlightState<V> {
+    Computing,
+    Done(V),
+    Abandoned,
+}
+
+impl<V: Clone> Flight<V> {
+    fn new() -> Flight<V> {
+        Flight {
+            state: Mutex::new(FlightState::Computing),
+            landed: Condvar::new(),
+        }
+    }
+
+    /* Blocks until the value is computed, None if the computing thread panicked */
+    fn wait(&self) -> Option<V> {
+        let mut state = lock(&self.state);
+        loop {
+            match &*state {
+                FlightState::Computing => {
+                    state = self
+                        .landed
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+                FlightState::Done(value) => return Some(value.clone()),
+                FlightState::Abandoned => return None,
+            }
+        }
+    }
+}
+
+/* Held by the thread computing a value. Removes the flight and wakes up the
+ * waiting threads when dropped, whether the function returned or panicked.
+ */
+struct Landing<'a, U, V>
+where
+    U: Eq + Hash,
+{
+    shard: &'a Shard<U, V>,
+    key: &'a U,
+    flight: &'a Flight<V>,
+    value: Option<V>,
+}
+
+impl<'a, U, V> Drop for Landing<'a, U, V>
+where
+    U: Eq + Hash,
+{
+    fn drop(&mut self) {
+        lock(&self.shard.in_flight).remove(self.key);
+        *lock(&self.flight.state) = match self.value.take() {
+            Some(value) => FlightState::Done(value),
+            None => FlightState::Abandoned,
+        };
+        self.flight.landed.notify_all();
+    }
+}
+
+/* None of the locks guard invariants a panic could break */
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /* Trivial, Copy-able memoization */
+    #[test]
+    fn memoization() {
+        let calls = AtomicUsize::new(0);
+        let add_two = SyncMemoizer::new(|n| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            n + 2
+        });
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(4, add_two.value(2));
+        assert_eq!(5, add_two.value(3));
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    /* Every thread sees the same values, once they are cached the function
+     * is not called again no matter which thread asks.
+     */
+    #[test]
+    fn shared_between_threads() {
+        let calls = AtomicUsize::new(0);
+        let square = SyncMemoizer::with_shards(4, |n: u64| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            n * n
+        });
+        for n in 0..100 {
+            square.value(n);
+        }
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for n in 0..100 {
+                        assert_eq!(n * n, square.value(n));
+                    }
+                });
+            }
+        });
+        assert_eq!(100, calls.load(Ordering::SeqCst));
+    }
+
+    /* Heap allocated keys and values across threads */
+    #[test]
+    fn heap_allocated() {
+        let reverse = SyncMemoizer::new(|s: String| s.chars().rev().collect::<String>());
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut reversed = reverse.value(String::from("gay"));
+                    assert_eq!("yag", reversed);
+                    reversed.push('!');
+                });
+            }
+        });
+        assert_eq!("yag", reverse.value(String::from("gay")));
+    }
+
+    /* Threads asking for a key which is already being computed wait for it */
+    #[test]
+    fn single_flight() {
+        let calls = AtomicUsize::new(0);
+        let (started_tx, started_rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+        let go_rx = Mutex::new(go_rx);
+        let slow = SyncMemoizer::new(|n: u64| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            started_tx.send(()).unwrap();
+            lock(&go_rx).recv().unwrap();
+            n * 2
+        });
+
+        thread::scope(|s| {
+            let leader = s.spawn(|| slow.value(21));
+            started_rx.recv().unwrap();
+            let followers: Vec<_> = (0..4).map(|_| s.spawn(|| slow.value(21))).collect();
+
+            thread::sleep(Duration::from_millis(50));
+            go_tx.send(()).unwrap();
+
+            assert_eq!(42, leader.join().unwrap());
+            for follower in followers {
+                assert_eq!(42, follower.join().unwrap());
+            }
+        });
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    /* If the computing thread panics a waiting thread takes over */
+    #[test]
+    fn leader_panics() {
+        let calls = AtomicUsize::new(0);
+        let (started_tx, started_rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+        let go_rx = Mutex::new(go_rx);
+        let flaky = SyncMemoizer::new(|n: u64| {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                started_tx.send(()).unwrap();
+                lock(&go_rx).recv().unwrap();
+                panic!("first call fails");
+            }
+            n * 2
+        });
+
+        thread::scope(|s| {
+            let leader = s.spawn(|| flaky.value(21));
+            started_rx.recv().unwrap();
+            let follower = s.spawn(|| flaky.value(21));
+
+            thread::sleep(Duration::from_millis(50));
+            go_tx.send(()).unwrap();
+
+            assert!(leader.join().is_err());
+            assert_eq!(42, follower.join().unwrap());
+        });
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        // Nothing is left in flight for the key
+        assert_eq!(42, flaky.value(21));
+        assert!(lock(&flaky.shards[flaky.shard(&21)].in_flight).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one shard")]
+    fn zero_shards() {
+        SyncMemoizer::with_shards(0, |n: u32| n);
+    }
+}